@@ -1,48 +1,240 @@
 use std::path::PathBuf;
 
-use chrono::NaiveDate;
+use crate::config::Config;
+use chrono::{
+    format::{Item, StrftimeItems},
+    Duration,
+};
 use clap::{
     self, arg, crate_authors, crate_version, value_parser, Arg, ArgAction, ArgGroup, Command,
 };
+use glob::Pattern;
+use log_cli::{
+    redact::Replacement,
+    timestamp::{self, Zone},
+    Encoding, IdPattern, Selection, SortBy, SortKey, StageKind,
+};
 
 const ABOUT: &str = "Command line interface for log files.";
 
-pub fn app() -> Command<'static> {
+/// Arguments of each group that takes at most one of its arguments.
+pub(crate) const EXCLUSIVE_ARGS: [&[&str]; 3] = [
+    &["line-range", "tail", "head"],
+    &["dedupe", "collapse"],
+    &["any", "all"],
+];
+
+/// Arguments that select, reorder or limit lines, which the pager doesn't apply.
+pub(crate) const VIEW_REJECTED_ARGS: [&str; 15] = [
+    "lines",
+    "line-range",
+    "head",
+    "tail",
+    "date-range",
+    "dedupe",
+    "collapse",
+    "sort",
+    "reverse",
+    "latest",
+    "first",
+    "skip",
+    "limit",
+    "context",
+    "pipeline",
+];
+
+/// When displayed lines are coloured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ColorMode {
+    /// Colour lines written to a terminal, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Parse a colour mode name.
+    pub(crate) fn parse(name: &str) -> Option<ColorMode> {
+        match name {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// Create the application, with the values set in `config` as defaults that arguments passed
+/// override. The application borrows its defaults from `config`.
+pub fn app(config: &Config) -> Command<'_> {
     let mut app = Command::new("log-cli")
         .author(crate_authors!())
         .version(crate_version!())
         .about(ABOUT)
-        .help_message("Prints help information. Use --help for more details.");
+        .help_message("Prints help information. Use --help for more details.")
+        .subcommand_negates_reqs(true);
+
+    app = with_query_args(app).arg(correlate_flag());
+
+    for subcommand in all_subcommands() {
+        app = app.subcommand(with_config(subcommand, config));
+    }
+
+    with_config(app, config)
+}
+
+/// Set the values of a config as the defaults of a command's arguments. Timestamp formats named
+/// in the config can be passed by name.
+fn with_config<'a>(command: Command<'a>, config: &'a Config) -> Command<'a> {
+    let mut command = command;
+    let ids: Vec<&'a str> = command
+        .get_arguments()
+        .map(Arg::get_id)
+        .filter(|id| config.defaults().contains_key(*id))
+        .collect();
+
+    for id in ids {
+        let values: Vec<&'a str> = config.defaults()[id].iter().map(String::as_str).collect();
+
+        command = command.mut_arg(id, |arg| arg.default_values(&values));
+    }
+
+    let formats = config.formats().clone();
+
+    if !formats.is_empty()
+        && command
+            .get_arguments()
+            .any(|arg| arg.get_id() == "timestamp-format")
+    {
+        command = command.mut_arg("timestamp-format", |arg| {
+            arg.value_parser(move |value: &str| {
+                parse_timestamp_format_value(formats.get(value).map_or(value, String::as_str))
+            })
+        });
+    }
+
+    command
+}
+
+/// Add the arguments selecting log files to a command.
+fn with_path_args<'a>(command: Command<'a>) -> Command<'a> {
+    let mut command = command;
+
+    for arg in path_args() {
+        command = command.arg(arg);
+    }
+
+    command
+}
+
+/// Add the arguments and groups used to build a query to a command.
+fn with_query_args<'a>(command: Command<'a>) -> Command<'a> {
+    let mut command = command;
 
     for arg in all_args() {
-        app = app.arg(arg);
+        command = command.arg(arg);
     }
 
     for group in all_groups() {
-        app = app.group(group);
+        command = command.group(group);
     }
 
-    app
+    command
 }
 
-fn all_args() -> Vec<Arg<'static>> {
+fn all_subcommands<'a>() -> Vec<Command<'a>> {
     vec![
-        paths_arg(),
+        patterns_command(),
+        gaps_command(),
+        spikes_command(),
+        view_command(),
+        index_command(),
+        redact_command(),
+    ]
+}
+
+fn patterns_command<'a>() -> Command<'a> {
+    with_query_args(Command::new("patterns"))
+        .about("Cluster queried lines into message templates.")
+        .long_about(
+            "\
+Cluster queried lines into message templates. Variable tokens (numbers, IDs, IPs, hex and quoted 
+strings) are masked and similar lines are merged, so each template is displayed once with its count, 
+first and last occurrence, and an example line number.",
+        )
+        .arg(similarity_flag())
+}
+
+fn all_args<'a>() -> Vec<Arg<'a>> {
+    let mut args = path_args();
+
+    args.extend([
         all_flag(),
         any_flag(),
+        color_flag(),
+        context_flag(),
+        collapse_flag(),
+        date_range_flag(),
+        dedupe_flag(),
+        encoding_flag(),
+        explain_flag(),
+        first_flag(),
         head_flag(),
+        ignore_timestamp_flag(),
+        input_tz_flag(),
         keywords_flag(),
         latest_flag(),
+        limit_flag(),
         line_range_flag(),
+        lines_flag(),
+        normalize_numbers_flag(),
+        output_tz_flag(),
+        pipeline_flag(),
+        redact_flag(),
+        reverse_flag(),
+        rotated_flag(),
+        segment_labels_flag(),
+        skip_flag(),
+        sort_flag(),
         tail_flag(),
+        timestamp_format_flag(),
+    ]);
+    args.extend(redact_args());
+
+    args
+}
+
+/// Arguments selecting the log files to read.
+fn path_args<'a>() -> Vec<Arg<'a>> {
+    vec![
+        paths_arg(),
+        binary_flag(),
+        exclude_path_flag(),
+        include_flag(),
+        recursive_flag(),
+        sort_files_flag(),
     ]
 }
 
-fn all_groups() -> Vec<ArgGroup<'static>> {
-    vec![evaluation_group(), range_group()]
+fn color_flag<'a>() -> Arg<'a> {
+    arg!(--color <WHEN>)
+        .required(false)
+        .value_parser(parse_color_value)
+        .default_value("auto")
+        .help("Colour lines by level: auto (default), always or never.")
+        .long_help(
+            "\
+Colour lines by level: auto (default), always or never. In auto, lines are coloured when written to a 
+terminal and NO_COLOR isn't set. Colours can be set by level in config files.",
+        )
+}
+
+fn all_groups<'a>() -> Vec<ArgGroup<'a>> {
+    vec![evaluation_group(), range_group(), repeats_group()]
 }
 
-fn paths_arg() -> Arg<'static> {
+fn paths_arg<'a>() -> Arg<'a> {
     arg!([LOG_FILE])
         .id("paths")
         .required(true)
@@ -52,11 +244,75 @@ fn paths_arg() -> Arg<'static> {
         .long_help(
             "\
 Path to log file to be read. By default if no additional flags are passed the entire file will be 
-displayed.",
+displayed. Directories and quoted glob patterns (ex: 'logs/**/*.log') are expanded to the files they 
+contain.",
         )
 }
 
-fn keywords_flag() -> Arg<'static> {
+fn recursive_flag<'a>() -> Arg<'a> {
+    Arg::new("recursive")
+        .short('r')
+        .long("recursive")
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Read the files of directories recursively.")
+}
+
+fn include_flag<'a>() -> Arg<'a> {
+    arg!(--include <GLOB>)
+        .required(false)
+        .multiple_values(true)
+        .min_values(1)
+        .value_parser(parse_glob_value)
+        .help("Only read expanded files matching a glob.")
+        .long_help(
+            "\
+Only read files expanded from directories and glob patterns that match a glob. Globs are matched 
+against file names and paths (ex: '*.log').",
+        )
+}
+
+fn exclude_path_flag<'a>() -> Arg<'a> {
+    arg!(--"exclude-path" <GLOB>)
+        .required(false)
+        .multiple_values(true)
+        .min_values(1)
+        .value_parser(parse_glob_value)
+        .help("Skip expanded files matching a glob.")
+        .long_help(
+            "\
+Skip files expanded from directories and glob patterns that match a glob. Globs are matched against 
+file names and paths (ex: '*.gz' '*/archive/*').",
+        )
+}
+
+fn binary_flag<'a>() -> Arg<'a> {
+    arg!(--binary)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Read expanded files that look binary.")
+        .long_help(
+            "\
+Read expanded files that look binary. Files expanded from directories and glob patterns are skipped 
+if they have a NUL byte near their start, unless they start with a UTF-16 byte order mark.",
+        )
+}
+
+fn sort_files_flag<'a>() -> Arg<'a> {
+    arg!(--"sort-files" <KEY>)
+        .required(false)
+        .value_parser(parse_sort_files_value)
+        .help("Sort every input by name or mtime.")
+        .long_help(
+            "\
+Sort every input by name or mtime (oldest first). By default inputs keep the order they were passed 
+in, and files expanded from each directory or glob pattern are sorted by name.",
+        )
+}
+
+fn keywords_flag<'a>() -> Arg<'a> {
     arg!(--keywords <VALUE>)
         .required(false)
         .multiple_values(true)
@@ -69,7 +325,7 @@ keywords).",
         )
 }
 
-fn line_range_flag() -> Arg<'static> {
+fn line_range_flag<'a>() -> Arg<'a> {
     arg!(--"line-range" <VALUE>)
         .required(false)
         .value_parser(parse_line_range_value)
@@ -84,7 +340,206 @@ through 10).",
         )
 }
 
-fn head_flag() -> Arg<'static> {
+fn lines_flag<'a>() -> Arg<'a> {
+    arg!(--lines <SLICES>)
+        .required(false)
+        .value_parser(parse_lines_value)
+        .allow_hyphen_values(true)
+        .help("Slices of line numbers to display.")
+        .long_help(
+            "\
+Comma separated Python-style slices of line numbers to display (ex: 10:20,100:,-50:). Slices select 
+lines from start up to, but excluding, stop. Negative indices count back from the end of the file, 
+missing indices are open-ended and a step samples lines (ex: ::10 for every 10th line). Can be 
+combined with --line-range, --head or --tail to also display the lines they select.",
+        )
+}
+
+fn date_range_flag<'a>() -> Arg<'a> {
+    arg!(--"date-range" <VALUE>)
+        .required(false)
+        .value_parser(parse_date_range_value)
+        .multiple_values(true)
+        .min_values(1)
+        .max_values(2)
+        .help("Date range of timestamped lines to display.")
+        .long_help(
+            "\
+Date range of timestamped lines to display. Values can be dates or datetimes (ex: 2022-01-01 
+\"2022-01-02 12:00\" to display lines from January 1st through noon on the 2nd). Lines without a 
+timestamp are not displayed.",
+        )
+}
+
+fn encoding_flag<'a>() -> Arg<'a> {
+    arg!(--encoding <VALUE>)
+        .required(false)
+        .value_parser(parse_encoding_value)
+        .help("Encoding to decode log files with.")
+        .long_help(
+            "\
+Encoding to decode log files with. Can be utf-8 (default), utf-16, utf-16le, utf-16be, latin-1 or 
+windows-1252. Files starting with a UTF-16 byte order mark are always decoded as UTF-16. Lines with 
+invalid bytes are displayed with replacement characters and reported.",
+        )
+}
+
+fn input_tz_flag<'a>() -> Arg<'a> {
+    arg!(--"input-tz" <ZONE>)
+        .required(false)
+        .value_parser(parse_zone_value)
+        .help("Timezone of timestamps logged without an offset.")
+        .long_help(
+            "\
+Timezone of timestamps logged without an offset, and of --date-range bounds. Can be UTC (default), 
+local, an offset (ex: +02:00) or a zone name (ex: Europe/Paris). Timestamps logged with an offset 
+(ex: 2022-01-01T07:00:00+02:00) are read in their own offset, so date ranges compare absolute times.",
+        )
+}
+
+fn timestamp_format_flag<'a>() -> Arg<'a> {
+    arg!(--"timestamp-format" <FORMAT>)
+        .required(false)
+        .value_parser(parse_timestamp_format_value)
+        .help("strftime format of the timestamps in the log file.")
+        .long_help(
+            "\
+strftime format of the timestamps in the log file (ex: \"%d.%m.%Y %H:%M:%S\"). By default timestamps 
+are found among the first tokens of each line and recognized as ISO 8601, RFC 3339, Python logging 
+(2022-01-01 07:00:00,0), RFC 2822, Apache ([10/Oct/2000:13:55:36 -0700]), syslog (Oct 11 22:14:15) 
+or epoch seconds and milliseconds.",
+        )
+}
+
+fn output_tz_flag<'a>() -> Arg<'a> {
+    arg!(--"output-tz" <ZONE>)
+        .required(false)
+        .value_parser(parse_zone_value)
+        .help("Timezone to display timestamps in.")
+        .long_help(
+            "\
+Timezone to display timestamps in. Leading timestamps are rewritten with their offset (ex: 
+2022-01-01 08:00:00+01:00). Takes the same values as --input-tz.",
+        )
+}
+
+fn context_flag<'a>() -> Arg<'a> {
+    arg!(-C --context <VALUE>)
+        .required(false)
+        .value_parser(value_parser!(usize))
+        .help("Display VALUE lines around each line kept.")
+}
+
+fn dedupe_flag<'a>() -> Arg<'a> {
+    arg!(--dedupe)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Drop lines repeating an earlier line.")
+}
+
+fn collapse_flag<'a>() -> Arg<'a> {
+    arg!(--collapse)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Fold consecutive repeated lines into one line.")
+        .long_help(
+            "\
+Fold consecutive repeated lines into their first line, annotated with the number of repeats and the 
+line numbers they span (ex: (repeated 437 times, ln120-ln556)).",
+        )
+}
+
+fn ignore_timestamp_flag<'a>() -> Arg<'a> {
+    arg!(--"ignore-timestamp")
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .requires("repeats")
+        .help("Ignore leading timestamps when finding repeated lines.")
+}
+
+fn normalize_numbers_flag<'a>() -> Arg<'a> {
+    arg!(--"normalize-numbers")
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .requires("repeats")
+        .help("Treat numbers as equal when finding repeated lines.")
+}
+
+fn pipeline_flag<'a>() -> Arg<'a> {
+    arg!(--pipeline <STAGES>)
+        .required(false)
+        .value_parser(parse_pipeline_value)
+        .help("Order to apply query stages in.")
+        .long_help(
+            "\
+Comma separated order to apply query stages in: select (--lines, --line-range, --head and --tail), 
+filter (--keywords and --date-range), dedupe (--dedupe and --collapse), sort (--sort), limit 
+(--latest, --first, --skip and --limit) and context (--context). Stages are applied in the order 
+their arguments are passed by default, so --keywords error --head 5 displays the first 5 error lines 
+while --head 5 --keywords error displays error lines among the first 5 lines. Stages not listed 
+follow in the order their arguments are passed.",
+        )
+}
+
+fn sort_flag<'a>() -> Arg<'a> {
+    arg!(--sort <KEY>)
+        .required(false)
+        .value_parser(parse_sort_value)
+        .help("Sort lines by timestamp, level or a field.")
+        .long_help(
+            "\
+Sort lines by timestamp, level or the value of a key=value field (ex: --sort took). Numeric field 
+values are compared as numbers. Lines keep their line numbers, and lines without the key are 
+displayed last. Lines past a memory budget are sorted in runs spilled to temporary files.",
+        )
+}
+
+fn reverse_flag<'a>() -> Arg<'a> {
+    arg!(--reverse)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .requires("sort")
+        .help("Sort lines in descending order.")
+}
+
+fn explain_flag<'a>() -> Arg<'a> {
+    arg!(--explain)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Display the query pipeline instead of running it.")
+}
+
+fn rotated_flag<'a>() -> Arg<'a> {
+    arg!(--rotated)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Read each log file with its rotated segments.")
+        .long_help(
+            "\
+Read each log file with its rotated segments as one file. Siblings named like the file with a number 
+or date suffix are segments (ex: app.log-20220101, app.log.2.gz and app.log.1 for app.log). Segments 
+are read oldest to newest, compressed segments are decompressed, and line numbers continue across 
+segments.",
+        )
+}
+
+fn segment_labels_flag<'a>() -> Arg<'a> {
+    arg!(--"segment-labels")
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .requires("rotated")
+        .help("Label each line with the rotated segment it came from.")
+}
+
+fn head_flag<'a>() -> Arg<'a> {
     arg!(--head <VALUE>)
         .default_missing_value("5")
         .required(false)
@@ -92,7 +547,7 @@ fn head_flag() -> Arg<'static> {
         .help("Display the top VALUE lines.")
 }
 
-fn tail_flag() -> Arg<'static> {
+fn tail_flag<'a>() -> Arg<'a> {
     arg!(--tail <VALUE>)
         .default_missing_value("5")
         .required(false)
@@ -100,7 +555,7 @@ fn tail_flag() -> Arg<'static> {
         .help("Display the bottom VALUE lines.")
 }
 
-fn all_flag() -> Arg<'static> {
+fn all_flag<'a>() -> Arg<'a> {
     arg!(--all)
         .required(false)
         .takes_value(false)
@@ -108,7 +563,7 @@ fn all_flag() -> Arg<'static> {
         .help("Set evaluation strategy to 'all'.")
 }
 
-fn any_flag() -> Arg<'static> {
+fn any_flag<'a>() -> Arg<'a> {
     arg!(--any)
         .required(false)
         .takes_value(false)
@@ -116,23 +571,264 @@ fn any_flag() -> Arg<'static> {
         .help("Set evaluation strategy to 'any'.")
 }
 
-fn latest_flag() -> Arg<'static> {
+fn latest_flag<'a>() -> Arg<'a> {
     arg!(--latest <VALUE>)
         .required(false)
         .value_parser(value_parser!(usize))
         .default_missing_value("1")
+        .conflicts_with_all(&["first", "skip", "limit"])
         .help("Set evaluation strategy to 'latest' VALUE lines.")
 }
 
-fn range_group() -> ArgGroup<'static> {
+fn first_flag<'a>() -> Arg<'a> {
+    arg!(--first <VALUE>)
+        .required(false)
+        .value_parser(value_parser!(usize))
+        .default_missing_value("1")
+        .conflicts_with("limit")
+        .help("Set evaluation strategy to 'first' VALUE lines.")
+        .long_help(
+            "\
+Set evaluation strategy to 'first' VALUE lines. Files stop being read once VALUE lines are kept 
+after filtering, so the first occurrence of a keyword in a large file is found immediately.",
+        )
+}
+
+fn skip_flag<'a>() -> Arg<'a> {
+    arg!(--skip <VALUE>)
+        .required(false)
+        .value_parser(value_parser!(usize))
+        .help("Skip the first VALUE lines kept after filtering.")
+}
+
+fn limit_flag<'a>() -> Arg<'a> {
+    arg!(--limit <VALUE>)
+        .required(false)
+        .value_parser(value_parser!(usize))
+        .help("Display at most VALUE lines kept after filtering and skipping.")
+        .long_help(
+            "\
+Display at most VALUE lines kept after filtering and skipping. Pass --skip with --limit to page 
+through results (ex: --skip 20 --limit 10 for the third page of 10 lines).",
+        )
+}
+
+fn view_command<'a>() -> Command<'a> {
+    with_query_args(Command::new("view"))
+        .about("Browse a log file in an interactive pager.")
+        .long_about(
+            "\
+Browse a log file in an interactive pager. Lines are paged from disk, so files larger than memory 
+can be viewed. Keywords passed are used as the initial filter. Arguments that select, reorder or 
+limit lines (ex: --tail or --date-range) can't be passed.
+
+Keys: j/k scroll, space/PageUp/PageDown page, g/G top/bottom, & edit filter, / search, n/N next/
+previous match, : jump to a line number or timestamp, c toggle context, m bookmark, b/B next/
+previous bookmark, q quit.",
+        )
+}
+
+fn index_command<'a>() -> Command<'a> {
+    with_path_args(Command::new("index"))
+        .about("Build sidecar indexes for fast repeat queries.")
+        .long_about(
+            "\
+Build sidecar indexes for fast repeat queries. Each index is written next to its log file and is 
+used automatically by queries until the log file changes size or modification time.",
+        )
+}
+
+fn redact_command<'a>() -> Command<'a> {
+    let mut command = with_path_args(Command::new("redact"))
+        .about("Write copies of log files with sensitive values redacted.")
+        .long_about(
+            "\
+Write copies of log files with sensitive values redacted. Emails, IP addresses, card numbers, bearer 
+tokens and user IDs are detected, along with the patterns of --redact-rules. Each copy is written 
+next to its file with a .redacted extension, or to --output-dir.",
+        )
+        .arg(encoding_flag())
+        .arg(output_dir_flag());
+
+    for arg in redact_args() {
+        command = command.arg(arg);
+    }
+
+    command
+}
+
+/// Arguments setting how sensitive values are redacted.
+fn redact_args<'a>() -> Vec<Arg<'a>> {
+    vec![redact_rules_flag(), redact_with_flag(), redact_key_flag()]
+}
+
+fn redact_flag<'a>() -> Arg<'a> {
+    arg!(--redact)
+        .required(false)
+        .takes_value(false)
+        .action(ArgAction::SetTrue)
+        .help("Redact sensitive values from the lines displayed.")
+        .long_help(
+            "\
+Redact sensitive values from the lines displayed. Emails, IP addresses, card numbers, bearer tokens 
+and user IDs are detected, along with the patterns of --redact-rules. Lines are filtered before they 
+are redacted. Passing --redact-rules or --redact-with also redacts lines.",
+        )
+}
+
+fn redact_rules_flag<'a>() -> Arg<'a> {
+    arg!(--"redact-rules" <FILE>)
+        .required(false)
+        .value_parser(value_parser!(PathBuf))
+        .help("File of regexes to redact, one per line.")
+        .long_help(
+            "\
+File of regexes to redact, one per line. Lines can name their rule with a prefix (ex: account = 
+acct-(\\d+)), and only the first capture group is redacted if a regex has one. Blank lines and lines 
+starting with # are skipped.",
+        )
+}
+
+fn redact_with_flag<'a>() -> Arg<'a> {
+    arg!(--"redact-with" <MODE>)
+        .required(false)
+        .value_parser(parse_replacement_value)
+        .help("Replace redacted values with a mask (default), hash or fake.")
+        .long_help(
+            "\
+Replace redacted values with a mask (ex: <email>), a hash (ex: <email:3fa2b1c9d0e4>) or a fake value 
+of the same shape. Hashes and fakes are keyed with --redact-key, so equal values are replaced 
+equally across files.",
+        )
+}
+
+fn redact_key_flag<'a>() -> Arg<'a> {
+    arg!(--"redact-key" <KEY>)
+        .required(false)
+        .help("Key hashes and fakes are derived with.")
+        .long_help(
+            "\
+Key hashes and fakes are derived with. Defaults to the LOG_CLI_REDACT_KEY environment variable, or 
+a random key, so replacements only match within a run.",
+        )
+}
+
+fn output_dir_flag<'a>() -> Arg<'a> {
+    arg!(--"output-dir" <DIR>)
+        .required(false)
+        .value_parser(value_parser!(PathBuf))
+        .help("Directory to write copies to instead of next to each file.")
+}
+
+fn correlate_flag<'a>() -> Arg<'a> {
+    arg!(--correlate <PATTERN>)
+        .required(false)
+        .value_parser(parse_id_pattern_value)
+        .help("Display every line sharing an ID with the lines matched, across all files.")
+        .long_help(
+            "\
+Display every line sharing an ID with the lines matched, across all files. The pattern is a field 
+name (ex: request_id reads request_id=<ID>) or a regex, whose first capture group or whole match is 
+the ID. Lines are grouped per ID and ordered by timestamp.",
+        )
+}
+
+fn gaps_command<'a>() -> Command<'a> {
+    with_query_args(Command::new("gaps"))
+        .about("Find gaps and out of order timestamps in queried lines.")
+        .long_about(
+            "\
+Find gaps and out of order timestamps in queried lines. Stretches longer than --gap without lines 
+logged, which usually mean a process hung, and timestamps earlier than the line before them are 
+displayed with the line numbers around them.",
+        )
+        .arg(gap_flag())
+}
+
+fn gap_flag<'a>() -> Arg<'a> {
+    arg!(--gap <DURATION>)
+        .required(false)
+        .value_parser(parse_duration_value)
+        .default_value("5m")
+        .help("Time without lines logged reported as a gap (ex: 30s, 5m or 1h).")
+}
+
+fn spikes_command<'a>() -> Command<'a> {
+    with_query_args(Command::new("spikes"))
+        .about("Find time buckets where the number of queried lines spiked.")
+        .long_about(
+            "\
+Find time buckets where the number of queried lines spiked. Lines are counted in buckets of --bucket 
+and each bucket is compared to a baseline of the --window buckets before it. Buckets more than 
+--z-score standard deviations above the baseline mean, or --factor times the mean, are displayed 
+with their count and an example line.",
+        )
+        .arg(bucket_flag())
+        .arg(window_flag())
+        .arg(z_score_flag())
+        .arg(factor_flag())
+}
+
+fn bucket_flag<'a>() -> Arg<'a> {
+    arg!(--bucket <DURATION>)
+        .required(false)
+        .value_parser(parse_duration_value)
+        .default_value("1m")
+        .help("Time span lines are counted in (ex: 30s, 1m or 1h).")
+}
+
+fn window_flag<'a>() -> Arg<'a> {
+    arg!(--window <VALUE>)
+        .required(false)
+        .value_parser(value_parser!(usize))
+        .default_value("10")
+        .help("Number of buckets before each bucket used as its baseline.")
+}
+
+fn z_score_flag<'a>() -> Arg<'a> {
+    arg!(--"z-score" <VALUE>)
+        .required(false)
+        .value_parser(parse_threshold_value)
+        .default_value("3")
+        .help("Standard deviations above the baseline mean a bucket must reach.")
+}
+
+fn factor_flag<'a>() -> Arg<'a> {
+    arg!(--factor <VALUE>)
+        .required(false)
+        .value_parser(parse_threshold_value)
+        .conflicts_with("z-score")
+        .help("Multiple of the baseline mean a bucket must reach, instead of a z-score.")
+}
+
+fn similarity_flag<'a>() -> Arg<'a> {
+    arg!(--similarity <VALUE>)
+        .required(false)
+        .value_parser(parse_similarity_value)
+        .default_value("0.5")
+        .help("Token similarity required to merge a line into a template.")
+        .long_help(
+            "\
+Token similarity required to merge a line into a template. Must be a value from 0 to 1 (ex: 1 to 
+only merge lines that are identical after masking).",
+        )
+}
+
+fn range_group<'a>() -> ArgGroup<'a> {
     ArgGroup::new("ranges")
-        .args(&["line-range", "tail", "head"])
+        .args(EXCLUSIVE_ARGS[0])
         .multiple(false)
 }
 
-fn evaluation_group() -> ArgGroup<'static> {
+fn repeats_group<'a>() -> ArgGroup<'a> {
+    ArgGroup::new("repeats")
+        .args(EXCLUSIVE_ARGS[1])
+        .multiple(false)
+}
+
+fn evaluation_group<'a>() -> ArgGroup<'a> {
     ArgGroup::new("primary-evaluations")
-        .args(&["any", "all"])
+        .args(EXCLUSIVE_ARGS[2])
         .multiple(false)
 }
 
@@ -148,39 +844,146 @@ pub fn parse_line_range_value(value: &str) -> Result<usize, String> {
     Ok(res)
 }
 
-/// Validate that a string is date-like.
-// NOTE: Currently strings are utilized internally. Evenaully `chrono` datetimes could be used,
-//       but for now we only use `chrono` for validation purposes.
-// TODO: Operate with `chrono` dates.
-fn _is_date_like(value: &str) -> Result<bool, String> {
-    // Attempt to parse string as date.
-    let res = NaiveDate::parse_from_str(value, "%Y-%m-%d");
+/// Parse lines argument value. Return the `Selection` of slices or error.
+pub fn parse_lines_value(value: &str) -> Result<Selection, String> {
+    Selection::parse(value).ok_or_else(|| {
+        format!(
+            "{} must be comma separated slices formatted as start:stop:step (ex: 10:20,-50:).",
+            value
+        )
+    })
+}
+
+/// Parse pipeline argument value. Return the stages listed in order or error.
+pub fn parse_pipeline_value(value: &str) -> Result<Vec<StageKind>, String> {
+    let mut res = vec![];
+
+    for name in value.split(',').map(str::trim) {
+        let kind = StageKind::parse(name).ok_or_else(|| {
+            format!(
+                "{} must be one of select, filter, dedupe, sort, limit or context.",
+                name
+            )
+        })?;
+
+        if res.contains(&kind) {
+            return Err(format!("{} is listed more than once.", name));
+        }
+
+        res.push(kind);
+    }
+
+    Ok(res)
+}
+
+/// Parse similarity argument value. Return a float between 0 and 1 or error.
+pub fn parse_similarity_value(value: &str) -> Result<f64, String> {
+    let res: f64 = value
+        .parse()
+        .map_err(|_| format!("{} must be a valid float.", value))?;
 
-    // If we fail to parse `NaiveDate` correctly return false.
-    if res.is_err() {
-        return Ok(false);
+    if !(0.0..=1.0).contains(&res) {
+        return Err(format!("{} must be between 0 and 1.", value));
     }
 
-    // Otherwise correctly return true.
-    Ok(true)
+    Ok(res)
 }
 
-/// Parse date range argument value(s). Return vector of at most 2 strings or error.
+/// Parse date range argument value(s). Return the validated string or error.
 /// A valid date range can be either one date string ("2022-01-01" for just January 1st) or two
-/// strings to indicate an inclusive range of dates ("2022-01-01" "2022-01-02").
-// TODO: Accept datetimes.
-pub fn _parse_date_range_value(value: &str) -> Result<String, String> {
-    // Check if value passed is a valid date formatted string.
-    if let Ok(false) = _is_date_like(value) {
-        return Err(format!("date format must be {}.", "%Y-%m-%d"));
+/// strings to indicate an inclusive range of dates ("2022-01-01" "2022-01-02"). Datetimes can be
+/// passed to narrow the range ("2022-01-01 07:00:00").
+pub fn parse_date_range_value(value: &str) -> Result<String, String> {
+    // Check if value passed is a valid date or datetime formatted string.
+    if timestamp::parse_bound(value, false).is_none() {
+        return Err(format!(
+            "{} must be formatted as %Y-%m-%d or %Y-%m-%d %H:%M:%S.",
+            value
+        ));
+    }
+
+    Ok(value.to_string())
+}
+
+/// Parse encoding argument value. Return the `Encoding` named or error.
+pub fn parse_encoding_value(value: &str) -> Result<Encoding, String> {
+    Encoding::parse(value).ok_or_else(|| {
+        format!(
+            "{} must be one of utf-8, utf-16, utf-16le, utf-16be, latin-1 or windows-1252.",
+            value
+        )
+    })
+}
+
+/// Parse a timezone argument value. Return the `Zone` named or error.
+pub fn parse_zone_value(value: &str) -> Result<Zone, String> {
+    Zone::parse(value).ok_or_else(|| {
+        format!(
+            "{} must be UTC, local, an offset (ex: +02:00) or a zone name (ex: Europe/Paris).",
+            value
+        )
+    })
+}
+
+/// Parse a timestamp format argument value. Return the format or error.
+pub fn parse_timestamp_format_value(value: &str) -> Result<String, String> {
+    if value.trim().is_empty() || StrftimeItems::new(value).any(|item| item == Item::Error) {
+        return Err(format!("{} must be a valid strftime format.", value));
     }
 
     Ok(value.to_string())
 }
 
+/// Parse a duration argument value. Return the `Duration` or error.
+pub fn parse_duration_value(value: &str) -> Result<Duration, String> {
+    timestamp::parse_duration(value)
+        .ok_or_else(|| format!("{} must be a duration in d, h, m, s or ms (ex: 5m).", value))
+}
+
+/// Parse a spike threshold argument value. Return a positive float or error.
+pub fn parse_threshold_value(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(res) if res.is_finite() && res > 0.0 => Ok(res),
+        _ => Err(format!("{} must be a positive number.", value)),
+    }
+}
+
+/// Parse an ID pattern argument value. Return a field name or regex pattern or error.
+pub fn parse_id_pattern_value(value: &str) -> Result<IdPattern, String> {
+    IdPattern::parse(value)
+        .map_err(|e| format!("{} must be a field name or valid regex: {}", value, e))
+}
+
+/// Parse a replacement argument value. Return the `Replacement` named or error.
+pub fn parse_replacement_value(value: &str) -> Result<Replacement, String> {
+    Replacement::parse(value).ok_or_else(|| format!("{} must be mask, hash or fake.", value))
+}
+
+/// Parse a colour mode argument value. Return the `ColorMode` named or error.
+pub fn parse_color_value(value: &str) -> Result<ColorMode, String> {
+    ColorMode::parse(value).ok_or_else(|| format!("{} must be auto, always or never.", value))
+}
+
+/// Parse a glob argument value. Return the compiled `Pattern` or error.
+pub fn parse_glob_value(value: &str) -> Result<Pattern, String> {
+    Pattern::new(value).map_err(|e| format!("{} must be a valid glob: {}.", value, e))
+}
+
+/// Parse sort files argument value. Return the `SortKey` named or error.
+pub fn parse_sort_files_value(value: &str) -> Result<SortKey, String> {
+    SortKey::parse(value).ok_or_else(|| format!("{} must be one of name or mtime.", value))
+}
+
+/// Parse sort argument value. Return the `SortBy` key named or error.
+pub fn parse_sort_value(value: &str) -> Result<SortBy, String> {
+    SortBy::parse(value)
+        .ok_or_else(|| format!("{} must be timestamp, level or a field name.", value))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::path::Path;
 
     #[test]
     fn parse_line_range() {
@@ -193,4 +996,134 @@ mod tests {
             Err(format!("{} must be a valid usize.", invalid_value))
         );
     }
+
+    #[test]
+    fn parse_lines() {
+        let selection = parse_lines_value("10:20,-50:,::10").unwrap();
+        let expected = Selection::parse("10:20, -50: , ::10").unwrap();
+
+        assert_eq!(selection, expected);
+        assert!(parse_lines_value("1:2:0").is_err());
+        assert!(parse_lines_value("a:").is_err());
+
+        // A single index past the largest line number can't be selected.
+        assert!(parse_lines_value("9223372036854775807").is_err());
+        assert_eq!(
+            parse_lines_value("9223372036854775806").unwrap(),
+            Selection::parse("9223372036854775806:9223372036854775807").unwrap()
+        );
+    }
+
+    #[test]
+    fn parse_pipeline() {
+        assert_eq!(
+            parse_pipeline_value("filter, select"),
+            Ok(vec![StageKind::Filter, StageKind::Select])
+        );
+        assert!(parse_pipeline_value("filter,filter").is_err());
+        assert!(parse_pipeline_value("grep").is_err());
+    }
+
+    #[test]
+    fn parse_similarity() {
+        assert_eq!(parse_similarity_value("0.4"), Ok(0.4));
+        assert_eq!(
+            parse_similarity_value("2"),
+            Err("2 must be between 0 and 1.".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_path_filters() {
+        assert!(parse_glob_value("*.log").is_ok());
+        assert!(parse_glob_value("[a").is_err());
+        assert_eq!(parse_sort_files_value("mtime"), Ok(SortKey::Modified));
+        assert!(parse_sort_files_value("size").is_err());
+        assert_eq!(parse_sort_value("level"), Ok(SortBy::Level));
+        assert_eq!(
+            parse_sort_value("took"),
+            Ok(SortBy::Field("took".to_string()))
+        );
+        assert!(parse_sort_value("a b").is_err());
+    }
+
+    #[test]
+    fn parse_zone() {
+        assert_eq!(parse_zone_value("utc"), Ok(Zone::Utc));
+        assert!(parse_zone_value("Europe/Paris").is_ok());
+        assert!(parse_zone_value("+25:00").is_err());
+    }
+
+    #[test]
+    fn parse_timestamp_format() {
+        assert!(parse_timestamp_format_value("%d/%b/%Y:%H:%M:%S %z").is_ok());
+        assert!(parse_timestamp_format_value("%Y-%m-%d %Q").is_err());
+    }
+
+    #[test]
+    fn parse_date_range() {
+        assert!(parse_date_range_value("2022-01-01").is_ok());
+        assert!(parse_date_range_value("2022-01-01 07:00:00").is_ok());
+        assert!(parse_date_range_value("01/01/2022").is_err());
+    }
+
+    #[test]
+    fn parse_encoding() {
+        assert_eq!(parse_encoding_value("Latin-1"), Ok(Encoding::Latin1));
+        assert!(parse_encoding_value("ebcdic").is_err());
+    }
+
+    #[test]
+    fn parse_id_pattern() {
+        assert!(matches!(
+            parse_id_pattern_value("trace_id"),
+            Ok(IdPattern::Field(_))
+        ));
+        assert!(matches!(
+            parse_id_pattern_value(r"req-(\d+)"),
+            Ok(IdPattern::Regex(_))
+        ));
+        assert!(parse_id_pattern_value("[").is_err());
+    }
+
+    #[test]
+    fn set_defaults_from_config() {
+        let text = "[defaults]\ncontext = 2\ndedupe = true\n[formats]\nshort = \"%d.%m %H:%M\"";
+        let config = Config::parse(text, Path::new("config.toml")).unwrap();
+
+        let matches =
+            app(&config).get_matches_from(["log-cli", "a.log", "--timestamp-format", "short"]);
+        assert_eq!(matches.get_one::<usize>("context"), Some(&2));
+        assert!(matches.get_flag("dedupe"));
+        assert_eq!(
+            matches.get_one::<String>("timestamp-format").unwrap(),
+            "%d.%m %H:%M"
+        );
+
+        let matches = app(&config).get_matches_from(["log-cli", "a.log", "-C", "5"]);
+        assert_eq!(matches.get_one::<usize>("context"), Some(&5));
+
+        let matches = app(&config).get_matches_from(["log-cli", "patterns", "a.log"]);
+        let (_, matches) = matches.subcommand().unwrap();
+        assert_eq!(matches.get_one::<usize>("context"), Some(&2));
+    }
+
+    #[test]
+    fn combine_lines_with_ranges() {
+        let config = Config::default();
+        let app = || app(&config);
+
+        assert!(app()
+            .try_get_matches_from(["log-cli", "a.log", "--lines", "0:2", "--tail", "2"])
+            .is_ok());
+        assert!(app()
+            .try_get_matches_from(["log-cli", "a.log", "--head", "2", "--tail", "2"])
+            .is_err());
+    }
+
+    #[test]
+    fn parse_replacement() {
+        assert_eq!(parse_replacement_value("Hash"), Ok(Replacement::Hash));
+        assert!(parse_replacement_value("shuffle").is_err());
+    }
 }
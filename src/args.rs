@@ -1,49 +1,368 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    io::{self, IsTerminal},
+    path::PathBuf,
+};
 
-use crate::{app, query, Result};
+use crate::{
+    app::{self, ColorMode},
+    config::Config,
+};
+use chrono::Duration;
+use clap::ValueSource;
+use glob::Pattern;
+use log_cli::{
+    redact::{self, Redactor, Replacement},
+    rotated,
+    timestamp::{self, Zone},
+    Encoding, Error, IdPattern, Inputs, Instructions, LineOrder, Palette, RepeatMode, Repeats,
+    Result, Selection, SortBy, SortKey, SpikeRule, StageKind, StdinSource, Threshold,
+};
+
+/// Path argument read as standard input.
+const STDIN_PATH: &str = "-";
+
+/// Names of the commands that can be run instead of a query.
+const COMMANDS: [&str; 6] = ["patterns", "gaps", "spikes", "view", "index", "redact"];
 
 /// Possible root arguments available to the user.
 pub(crate) enum Root {
     /// Query a log file using query instructions.
     Query,
+    /// Cluster the lines of a query into message templates.
+    Patterns,
+    /// Query log files, then display every line sharing an ID with the lines matched.
+    Correlate,
+    /// Find gaps and inversions in the timestamps of the lines of a query.
+    Gaps,
+    /// Find time buckets where the number of lines of a query spiked.
+    Spikes,
+    /// Browse a log file in an interactive pager.
+    View,
+    /// Build sidecar indexes for log files.
+    Index,
+    /// Write copies of log files with sensitive values redacted.
+    Redact,
 }
 
-/// Args wrapper around clap data.
-pub(crate) struct Args(clap::ArgMatches);
+/// Args wrapper around clap data. The raw arguments are kept to tell the order flags were
+/// passed in, and the config to tell which values it set.
+pub(crate) struct Args(clap::ArgMatches, Vec<String>, Config);
 
 impl Args {
     /// Get matches from clap and wrap in `Args` struct.
     pub(crate) fn parse() -> Result<Args> {
-        let matches = app::app().get_matches();
+        let mut raw: Vec<String> = env::args_os()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut config = Config::load()?;
 
-        if !matches.contains_id("paths") {
-            return Err(From::from("no paths were found"));
+        // Saved queries are run by name after an @ (ex: @payments-errors).
+        if let Some(name) = take_saved_query(&mut raw) {
+            config = config.select(&name)?;
+        }
+
+        // Values set for a group that takes one argument are dropped once one is passed.
+        for group in app::EXCLUSIVE_ARGS {
+            if group.iter().any(|id| position(&raw, id).is_some()) {
+                for id in group {
+                    config = config.without(id);
+                }
+            }
         }
 
-        Ok(Args(matches))
+        let app = app::app(&config);
+        let known = |id: &str| {
+            id != "paths"
+                && std::iter::once(&app)
+                    .chain(app.get_subcommands())
+                    .any(|command| command.get_arguments().any(|arg| arg.get_id() == id))
+        };
+
+        if let Some(id) = config.defaults().keys().find(|id| !known(id)) {
+            return Err(Error::Args(format!("{} can't be set in config", id)));
+        }
+
+        let args = Args(app.get_matches_from(&raw), raw, config);
+
+        if !args.matches().contains_id("paths") {
+            return Err(Error::Args("no paths were found".to_string()));
+        }
+
+        Ok(args)
     }
 
     /// Get the root argument from `Args`.
     pub(crate) fn root(&self) -> Result<Root> {
-        Ok(Root::Query)
+        match self.0.subcommand_name() {
+            None if self.correlate().is_some() => Ok(Root::Correlate),
+            None => Ok(Root::Query),
+            Some("patterns") => Ok(Root::Patterns),
+            Some("gaps") => Ok(Root::Gaps),
+            Some("spikes") => Ok(Root::Spikes),
+            Some("view") => Ok(Root::View),
+            Some("index") => Ok(Root::Index),
+            Some("redact") => Ok(Root::Redact),
+            Some(name) => Err(Error::Args(format!("unknown command {}", name))),
+        }
     }
 
-    // Access to clap matches.
+    // Access to clap matches. Subcommands carry their own query arguments, so their matches are
+    // used when one is selected.
     fn matches(&self) -> &clap::ArgMatches {
-        &self.0
+        match self.0.subcommand() {
+            Some((_, matches)) => matches,
+            None => &self.0,
+        }
+    }
+
+    /// Check that none of the arguments `ids` were passed to a command that doesn't apply them.
+    pub(crate) fn reject(&self, ids: &[&str], command: &str) -> Result<()> {
+        let matches = self.matches();
+
+        match ids
+            .iter()
+            .find(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+        {
+            Some(id) => Err(Error::Args(format!(
+                "--{} can't be passed to {}",
+                id, command
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the similarity threshold used to merge templates.
+    pub(crate) fn similarity(&self) -> f64 {
+        self.matches()
+            .get_one::<f64>("similarity")
+            .copied()
+            .unwrap_or(0.5)
+    }
+
+    /// Get the time without lines logged reported as a gap.
+    pub(crate) fn gap(&self) -> Duration {
+        self.matches()
+            .get_one::<Duration>("gap")
+            .copied()
+            .unwrap_or_else(|| Duration::minutes(5))
+    }
+
+    /// Get the pattern IDs are read with to correlate lines, if any.
+    pub(crate) fn correlate(&self) -> Option<IdPattern> {
+        self.matches().get_one::<IdPattern>("correlate").cloned()
+    }
+
+    /// Get the rule used to find spikes. Spikes are found by z-score unless a factor was passed.
+    pub(crate) fn spike_rule(&self) -> SpikeRule {
+        let matches = self.matches();
+        let bucket = matches
+            .get_one::<Duration>("bucket")
+            .copied()
+            .unwrap_or_else(|| Duration::minutes(1));
+        let window = matches.get_one::<usize>("window").copied().unwrap_or(10);
+        let threshold = match matches.get_one::<f64>("factor") {
+            Some(factor) => Threshold::Factor(*factor),
+            None => Threshold::ZScore(matches.get_one::<f64>("z-score").copied().unwrap_or(3.0)),
+        };
+
+        SpikeRule::new(bucket, window, threshold)
+    }
+
+    /// Get the redactor sensitive values are redacted with, if redaction was asked for. Passing
+    /// rules or a replacement asks for redaction too.
+    pub(crate) fn redactor(&self) -> Result<Option<Redactor>> {
+        let matches = self.matches();
+        let flagged = matches.try_get_one::<bool>("redact").ok().flatten() == Some(&true);
+        let rules = matches.get_one::<PathBuf>("redact-rules");
+        let replacement = matches.get_one::<Replacement>("redact-with");
+
+        if !flagged
+            && rules.is_none()
+            && replacement.is_none()
+            && self.0.subcommand_name() != Some("redact")
+        {
+            return Ok(None);
+        }
+
+        let mut redactor = Redactor::new(replacement.copied().unwrap_or_default());
+
+        let key = matches
+            .get_one::<String>("redact-key")
+            .cloned()
+            .or_else(|| env::var(redact::KEY_VARIABLE).ok());
+        if let Some(key) = key {
+            redactor = redactor.with_key(&key);
+        }
+
+        if let Some(path) = rules {
+            redactor = redactor.with_rules_file(path)?;
+        }
+
+        Ok(Some(redactor))
+    }
+
+    /// Get the directory redacted copies are written to, if one was passed.
+    pub(crate) fn output_dir(&self) -> Option<PathBuf> {
+        self.matches().get_one::<PathBuf>("output-dir").cloned()
+    }
+
+    /// Get the colours lines are displayed in, if they're coloured.
+    pub(crate) fn palette(&self) -> Option<Palette> {
+        let colored = match self.matches().get_one::<ColorMode>("color") {
+            Some(ColorMode::Always) => true,
+            Some(ColorMode::Auto) => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+            }
+            _ => false,
+        };
+
+        colored.then(|| self.2.palette())
+    }
+
+    /// Get the encoding passed, if any.
+    pub(crate) fn encoding(&self) -> Option<Encoding> {
+        self.matches().get_one::<Encoding>("encoding").copied()
+    }
+
+    /// Get the paths passed. Directories and glob patterns are expanded to the files they
+    /// contain.
+    pub(crate) fn paths(&self) -> Result<Vec<PathBuf>> {
+        let args: Vec<PathBuf> = self
+            .matches()
+            .get_many::<PathBuf>("paths")
+            .map(|paths| paths.cloned().collect())
+            .unwrap_or_default();
+        let paths = self.inputs().expand(&args)?;
+
+        if paths.is_empty() {
+            return Err(Error::Args("no log files were found".to_string()));
+        }
+
+        Ok(paths)
+    }
+
+    /// Create the options used to expand path arguments from `Args`.
+    fn inputs(&self) -> Inputs {
+        let matches = self.matches();
+
+        // UTF-16 text has NUL bytes, so it would look binary without a byte order mark.
+        let utf16 = matches
+            .try_get_one::<Encoding>("encoding")
+            .ok()
+            .flatten()
+            .is_some_and(|e| !e.is_byte_oriented());
+        let mut inputs = Inputs::new()
+            .with_recursive(matches.get_flag("recursive"))
+            .with_binary(matches.get_flag("binary") || utf16);
+
+        if let Some(patterns) = matches.get_many::<Pattern>("include") {
+            for pattern in patterns {
+                inputs = inputs.add_include(pattern.to_owned());
+            }
+        }
+
+        if let Some(patterns) = matches.get_many::<Pattern>("exclude-path") {
+            for pattern in patterns {
+                inputs = inputs.add_exclude(pattern.to_owned());
+            }
+        }
+
+        if let Some(sort) = matches.get_one::<SortKey>("sort-files") {
+            inputs = inputs.with_sort(*sort);
+        }
+
+        inputs
+    }
+
+    /// Check if the query plan should be displayed instead of running the query.
+    pub(crate) fn explain(&self) -> bool {
+        self.matches().get_flag("explain")
+    }
+
+    /// Get the order to apply query stages in. Stages listed with `--pipeline` come first, and the
+    /// rest follow in the order their arguments were passed. Stages listed must have arguments.
+    fn stage_order(&self) -> Result<Vec<StageKind>> {
+        let matches = self.matches();
+
+        // Flags given without a value have no index in clap's matches, so passed flags are found
+        // in the raw arguments.
+        let passed_at = |ids: &[&str]| {
+            ids.iter()
+                .filter(|id| matches.value_source(id) == Some(ValueSource::CommandLine))
+                .filter_map(|id| position(&self.1, id))
+                .min()
+        };
+        let configured = |ids: &[&str]| {
+            ids.iter().any(|id| {
+                matches.value_source(id) == Some(ValueSource::DefaultValue)
+                    && self.2.defaults().contains_key(*id)
+            })
+        };
+
+        let stages = [
+            (
+                StageKind::Select,
+                &["lines", "line-range", "head", "tail"][..],
+            ),
+            (StageKind::Filter, &["keywords", "date-range"]),
+            (StageKind::Dedupe, &["dedupe", "collapse"]),
+            (StageKind::Sort, &["sort", "reverse"]),
+            (StageKind::Limit, &["latest", "first", "skip", "limit"]),
+            (StageKind::Context, &["context"]),
+        ];
+        let mut passed: Vec<(usize, StageKind)> = stages
+            .iter()
+            .filter_map(|(kind, ids)| passed_at(ids).map(|i| (i, *kind)))
+            .collect();
+        passed.sort_by_key(|(i, _)| *i);
+
+        let mut order = matches
+            .get_one::<Vec<StageKind>>("pipeline")
+            .cloned()
+            .unwrap_or_default();
+
+        // Stages with arguments set in config can be listed, and otherwise keep their default place.
+        let has_args = |kind: &StageKind| {
+            passed.iter().any(|(_, p)| p == kind)
+                || stages.iter().any(|(k, ids)| k == kind && configured(ids))
+        };
+
+        if let Some(kind) = order.iter().find(|k| !has_args(k)) {
+            return Err(Error::Args(format!(
+                "pipeline stage {} has no arguments",
+                kind.name()
+            )));
+        }
+
+        for (_, kind) in passed {
+            if !order.contains(&kind) {
+                order.push(kind);
+            }
+        }
+
+        Ok(order)
     }
 
     /// Create query instructions from `Args`.
-    pub(crate) fn to_instructions(&self) -> Result<query::Instructions> {
-        let mut instructions = query::Instructions::new();
+    pub(crate) fn to_instructions(&self) -> Result<Instructions> {
+        let mut instructions = Instructions::new();
 
-        // Add paths found to instructions.
-        if let Some(paths) = self.matches().get_many::<PathBuf>("paths") {
-            for path in paths {
+        let rotated = self.matches().get_flag("rotated");
+        let paths = self.paths()?;
+
+        // Add paths found to instructions. Rotated segments of another path are read with it.
+        for path in paths.iter() {
+            if path.as_os_str() == STDIN_PATH {
+                instructions = instructions.add_source(StdinSource::new());
+            } else if !(rotated && paths.iter().any(|base| rotated::is_segment_of(path, base))) {
                 instructions = instructions.add_path(path.to_owned());
             }
         }
 
+        instructions =
+            instructions.with_rotated(rotated, self.matches().get_flag("segment-labels"));
+
         // Add keywords found to instructions.
         if let Some(keywords) = self.matches().get_many::<String>("keywords") {
             for word in keywords {
@@ -53,12 +372,12 @@ impl Args {
 
         // Add head range to instructions if found.
         if let Some(n) = self.matches().get_one::<usize>("head") {
-            instructions = instructions.add_relative_range("head", *n);
+            instructions = instructions.add_head(*n);
         }
 
         // Add tail range to instructions if found.
         if let Some(n) = self.matches().get_one::<usize>("tail") {
-            instructions = instructions.add_relative_range("tail", *n);
+            instructions = instructions.add_tail(*n);
         }
 
         // Add line range to instructions if found.
@@ -66,31 +385,211 @@ impl Args {
             let mut vals = range;
             let lower = vals.next();
             if lower.is_none() {
-                return Err(From::from("at least one value is required for line-range"));
+                return Err(Error::Args(
+                    "at least one value is required for line-range".to_string(),
+                ));
             }
             let upper = vals.next().unwrap_or_else(|| lower.unwrap());
-            instructions = instructions.add_range("line-range", *lower.unwrap(), *upper);
+            instructions = instructions.add_line_range(*lower.unwrap(), *upper)?;
+        }
+
+        // Add line selection to instructions if found.
+        if let Some(selection) = self.matches().get_one::<Selection>("lines") {
+            instructions = instructions.add_selection(selection.to_owned());
+        }
+
+        // Add date range to instructions if found.
+        if let Some(range) = self.matches().get_many::<String>("date-range") {
+            let mut vals = range;
+            let lower = vals.next().ok_or_else(|| {
+                Error::Args("at least one value is required for date-range".to_string())
+            })?;
+            let upper = vals.next().unwrap_or(lower);
+            let parse = |value: &str, upper| {
+                timestamp::parse_bound(value, upper)
+                    .ok_or_else(|| Error::Parse(format!("invalid date-range value {}", value)))
+            };
+
+            instructions = instructions.add_date_range(parse(lower, false)?, parse(upper, true)?);
+        }
+
+        // Add repeat reduction to instructions if found.
+        let mode = match (
+            self.matches().get_flag("dedupe"),
+            self.matches().get_flag("collapse"),
+        ) {
+            (true, _) => Some(RepeatMode::Drop),
+            (_, true) => Some(RepeatMode::Collapse),
+            _ => None,
+        };
+
+        if let Some(mode) = mode {
+            let repeats = Repeats::new(mode)
+                .with_ignore_timestamp(self.matches().get_flag("ignore-timestamp"))
+                .with_normalize_numbers(self.matches().get_flag("normalize-numbers"));
+            instructions = instructions.with_repeats(repeats);
+        }
+
+        // Add line order to instructions if found.
+        if let Some(by) = self.matches().get_one::<SortBy>("sort") {
+            let order = LineOrder::new(by.to_owned(), self.matches().get_flag("reverse"));
+            instructions = instructions.with_sort(order);
+        }
+
+        // Add first eval to instructions if found.
+        if let Some(eval) = self.matches().get_one::<usize>("first") {
+            instructions = instructions.with_first(*eval);
+        }
+
+        // Add skip to instructions if found.
+        if let Some(n) = self.matches().get_one::<usize>("skip") {
+            instructions = instructions.with_skip(*n);
+        }
+
+        // Add limit to instructions if found.
+        if let Some(n) = self.matches().get_one::<usize>("limit") {
+            instructions = instructions.with_limit(*n);
+        }
+
+        // Add context to instructions if found.
+        if let Some(n) = self.matches().get_one::<usize>("context") {
+            instructions = instructions.with_context(*n);
+        }
+
+        instructions = instructions.with_order(self.stage_order()?);
+
+        // Add how timestamps are read and displayed to instructions if found.
+        if let Some(zone) = self.matches().get_one::<Zone>("input-tz") {
+            instructions = instructions.with_input_tz(*zone);
+        }
+
+        if let Some(format) = self.matches().get_one::<String>("timestamp-format") {
+            instructions = instructions.with_timestamp_format(format.to_owned());
+        }
+
+        if let Some(zone) = self.matches().get_one::<Zone>("output-tz") {
+            instructions = instructions.with_output_tz(*zone);
+        }
+
+        // Add encoding to instructions if found.
+        if let Some(encoding) = self.encoding() {
+            instructions = instructions.with_encoding(encoding);
+        }
+
+        if let Some(redactor) = self.redactor()? {
+            instructions = instructions.with_redactor(redactor);
         }
 
         // Add all eval to instructions if found.
         if let Some(eval) = self.matches().get_one::<bool>("all") {
             if *eval {
-                instructions = instructions.add_eval("all", None);
+                instructions = instructions.match_all();
             }
         }
 
         // Add any eval to instructions if found.
         if let Some(eval) = self.matches().get_one::<bool>("any") {
             if *eval {
-                instructions = instructions.add_eval("any", None);
+                instructions = instructions.match_any();
             }
         }
 
         // Add latest eval to instructions if found.
         if let Some(eval) = self.matches().get_one::<usize>("latest") {
-            instructions = instructions.add_eval("latest", Some(*eval));
+            instructions = instructions.with_latest(*eval);
         }
 
         Ok(instructions)
     }
 }
+
+/// Remove the name of a saved query from the raw arguments and return it. Saved queries are
+/// named in the first argument, or the first argument of a command (ex: `@payments-errors` or
+/// `view @payments-errors`), so values of other arguments can start with an @.
+fn take_saved_query(raw: &mut Vec<String>) -> Option<String> {
+    let i = match raw.get(1) {
+        Some(arg) if COMMANDS.contains(&arg.as_str()) => 2,
+        _ => 1,
+    };
+    let arg = raw.get(i)?;
+
+    match arg.strip_prefix('@') {
+        Some(name) if !name.is_empty() => {
+            let name = name.to_string();
+            raw.remove(i);
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// Find the position of an argument passed in raw arguments.
+fn position(raw: &[String], id: &str) -> Option<usize> {
+    let long = format!("--{}", id);
+    let short = (id == "context").then_some("-C");
+
+    raw.iter().position(|arg| {
+        arg == &long
+            || arg.starts_with(&format!("{}=", long))
+            || short.is_some_and(|short| arg.starts_with(short))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn take_saved_query_name() {
+        let mut args = raw(&["log-cli", "@payments-errors", "app.log"]);
+        assert_eq!(
+            take_saved_query(&mut args),
+            Some("payments-errors".to_string())
+        );
+        assert_eq!(args, raw(&["log-cli", "app.log"]));
+
+        let mut args = raw(&["log-cli", "view", "@payments-errors", "app.log"]);
+        assert_eq!(
+            take_saved_query(&mut args),
+            Some("payments-errors".to_string())
+        );
+        assert_eq!(args, raw(&["log-cli", "view", "app.log"]));
+
+        // Values of other arguments starting with an @ aren't saved queries.
+        let mut args = raw(&["log-cli", "sample.log", "--keywords", "@admin"]);
+        assert_eq!(take_saved_query(&mut args), None);
+        assert_eq!(args.len(), 4);
+
+        let mut args = raw(&["log-cli", "@"]);
+        assert_eq!(take_saved_query(&mut args), None);
+    }
+
+    #[test]
+    fn reject_args_passed_to_view() {
+        let parse = |args: &[&str]| {
+            let raw = raw(args);
+            let config = Config::default();
+            Args(app::app(&config).get_matches_from(&raw), raw, config)
+        };
+
+        let args = parse(&["log-cli", "view", "app.log", "--keywords", "error"]);
+        assert!(args.reject(&app::VIEW_REJECTED_ARGS, "view").is_ok());
+
+        for flag in [
+            "--tail",
+            "--head",
+            "--line-range=5",
+            "--date-range=2022-01-01",
+        ] {
+            let args = parse(&["log-cli", "view", "app.log", flag]);
+            assert!(matches!(
+                args.reject(&app::VIEW_REJECTED_ARGS, "view"),
+                Err(Error::Args(_))
+            ));
+        }
+    }
+}
@@ -1,9 +1,18 @@
+use crate::encoding::Encoding;
+use flate2::read::MultiGzDecoder;
+use memmap2::Mmap;
 use std::{
     fs::File,
-    io::{BufReader, Result},
+    io::{BufRead, BufReader, Read, Result, Seek, SeekFrom},
     path::Path,
 };
 
+/// Extension of gzip compressed files.
+const GZIP_EXTENSION: &str = "gz";
+
+/// Bytes read at a time when scanning a file backwards.
+const CHUNK_SIZE: usize = 64 * 1024;
+
 /// Create a `File` `BufReader` from a `Path`. Otherwise throw IO Error.
 pub(crate) fn read_file<P>(path: P) -> Result<BufReader<File>>
 where
@@ -12,3 +21,185 @@ where
     let file = File::open(path)?;
     Ok(BufReader::new(file))
 }
+
+/// Memory-map a regular file for reading its raw bytes. Return `None` for anything else (ex:
+/// pipes), which must be read as a stream.
+pub(crate) fn map_file<P>(path: P) -> Result<Option<Mmap>>
+where
+    P: AsRef<Path>,
+{
+    let file = File::open(path)?;
+
+    if !file.metadata()?.is_file() {
+        return Ok(None);
+    }
+
+    // SAFETY: The map is only read. Like any reader, results are undefined if the file is
+    // truncated while it is being read.
+    let map = unsafe { Mmap::map(&file)? };
+
+    Ok(Some(map))
+}
+
+/// Check if a file is gzip compressed, going by its extension.
+pub(crate) fn is_compressed<P>(path: P) -> bool
+where
+    P: AsRef<Path>,
+{
+    path.as_ref()
+        .extension()
+        .is_some_and(|ext| ext == GZIP_EXTENSION)
+}
+
+/// Read the decompressed bytes of a gzip file. Files of several gzip members (ex: files joined
+/// with cat) are read in full.
+pub(crate) fn read_compressed<P>(path: P) -> Result<Vec<u8>>
+where
+    P: AsRef<Path>,
+{
+    let mut bytes = vec![];
+
+    MultiGzDecoder::new(read_file(path)?).read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Read the raw bytes of the next line from a reader into `buf` without its line ending. Return
+/// `false` at the end of the reader.
+pub(crate) fn read_raw_line<R>(reader: &mut R, buf: &mut Vec<u8>) -> Result<bool>
+where
+    R: BufRead,
+{
+    buf.clear();
+
+    if reader.read_until(b'\n', buf)? == 0 {
+        return Ok(false);
+    }
+
+    trim_line_ending(buf);
+
+    Ok(true)
+}
+
+/// Remove the line ending of a line read with `read_until`, so it has the bytes lines are matched
+/// against.
+pub(crate) fn trim_line_ending(buf: &mut Vec<u8>) {
+    if buf.ends_with(b"\n") {
+        buf.pop();
+    }
+    if buf.ends_with(b"\r") {
+        buf.pop();
+    }
+}
+
+/// Read the next line from a reader without its line ending. Invalid bytes are replaced rather
+/// than dropped. Return `None` at the end of the reader.
+pub(crate) fn read_line<R>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    encoding: Encoding,
+) -> Result<Option<String>>
+where
+    R: BufRead,
+{
+    if !read_raw_line(reader, buf)? {
+        return Ok(None);
+    }
+
+    Ok(Some(encoding.decode(buf).0))
+}
+
+/// Count the lines starting between two byte offsets of a reader. `start` must be the offset of
+/// a line. Only the bytes between the offsets are read.
+pub(crate) fn count_lines_between<R>(reader: &mut R, start: u64, end: u64) -> Result<usize>
+where
+    R: BufRead + Seek,
+{
+    let mut offset = reader.seek(SeekFrom::Start(start))?;
+    let mut line = vec![];
+    let mut count = 0;
+
+    while offset < end {
+        line.clear();
+
+        let n = reader.read_until(b'\n', &mut line)?;
+        if n == 0 {
+            break;
+        }
+
+        offset += n as u64;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Find the byte offset where the last `n` lines of a file start by scanning backwards from the
+/// end of the file. Only a chunk of the file is held in memory at a time.
+pub(crate) fn tail_offset(file: &mut File, n: usize) -> Result<u64> {
+    let len = file.seek(SeekFrom::End(0))?;
+    let mut chunk = vec![0; CHUNK_SIZE];
+    let mut end = len;
+    let mut found = 0;
+
+    if n == 0 {
+        return Ok(len);
+    }
+
+    while end > 0 {
+        let start = end.saturating_sub(CHUNK_SIZE as u64);
+        let size = (end - start) as usize;
+
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut chunk[..size])?;
+
+        for (i, b) in chunk[..size].iter().enumerate().rev() {
+            let offset = start + i as u64;
+
+            // The line ending of the last line doesn't start a new line.
+            if *b != b'\n' || offset == len - 1 {
+                continue;
+            }
+
+            found += 1;
+            if found == n {
+                return Ok(offset + 1);
+            }
+        }
+
+        end = start;
+    }
+
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    #[test]
+    fn count_and_tail_lines() {
+        let dir = TestDir::new("buffer");
+        let path = dir.join("app.log");
+        let lines: Vec<String> = (0..20_000).map(|i| format!("line {}", i)).collect();
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut reader = read_file(&path).unwrap();
+        let offset = tail_offset(reader.get_mut(), 2).unwrap();
+        assert_eq!(count_lines_between(&mut reader, 0, offset).unwrap(), 19_998);
+        assert_eq!(count_lines_between(&mut reader, 7, 21).unwrap(), 2);
+
+        reader.seek(SeekFrom::Start(offset)).unwrap();
+
+        let mut buf = vec![];
+        assert_eq!(
+            read_line(&mut reader, &mut buf, Encoding::Utf8)
+                .unwrap()
+                .unwrap(),
+            "line 19998"
+        );
+        assert_eq!(tail_offset(reader.get_mut(), 30_000).unwrap(), 0);
+    }
+}
@@ -0,0 +1,251 @@
+use crate::args::Args;
+use log_cli::{Error, Result};
+use std::path::PathBuf;
+
+/// Parse the arguments of the process and run the command selected. Return `Error::NoMatch` if
+/// a query matched no lines, so callers can exit like grep.
+pub(crate) fn run() -> Result<()> {
+    Args::parse().and_then(run_root)
+}
+
+/// Determine root behavior selected.
+fn run_root(args: Args) -> Result<()> {
+    use crate::args::Root::*;
+
+    match args.root()? {
+        Query => run_query(args),
+        Correlate => run_correlate(args),
+        Patterns => run_patterns(args),
+        Gaps => run_gaps(args),
+        Spikes => run_spikes(args),
+        View => run_view(args),
+        Index => run_index(args),
+        Redact => run_redact(args),
+    }
+}
+
+/// Execute the main query behavior.
+fn run_query(args: Args) -> Result<()> {
+    use log_cli::{Query, TextSink};
+    use std::io;
+
+    let query = Query::build(args.to_instructions()?)?;
+
+    if args.explain() {
+        println!("{}", query.pipeline());
+        return Ok(());
+    }
+
+    let mut sink = TextSink::new(io::stdout().lock());
+
+    if let Some(palette) = args.palette() {
+        sink = sink.with_palette(palette);
+    }
+
+    query.execute(&mut sink)
+}
+
+/// Execute a query, then display every line across its paths sharing an ID with its results.
+fn run_correlate(args: Args) -> Result<()> {
+    use log_cli::{Correlation, Query};
+
+    let pattern = args
+        .correlate()
+        .ok_or_else(|| Error::Args("no ID pattern was found".to_string()))?;
+    let query = Query::build(args.to_instructions()?)?;
+
+    if args.explain() {
+        println!("{}", query.pipeline());
+        println!(
+            "Lines sharing an ID by {} with the results are then read from every file.",
+            pattern
+        );
+        return Ok(());
+    }
+
+    let mut correlation = Correlation::new(pattern);
+    let mut read = vec![];
+
+    // Paths that fail are reported once, and left out when lines are correlated.
+    let partial = match query.for_each_path(|_, path, res| {
+        correlation.add_ids(res.enumerated_lines());
+        read.push(path.to_path_buf());
+
+        Ok(())
+    }) {
+        Ok(()) | Err(Error::NoMatch) => None,
+        Err(e @ Error::Partial { .. }) => Some(e),
+        Err(e) => return Err(e),
+    };
+
+    if correlation.is_empty() {
+        return Err(partial.unwrap_or(Error::NoMatch));
+    }
+
+    let related = query.unfiltered(read)?;
+
+    related.for_each_path(|_, path, res| {
+        correlation.add_lines(path, res.enumerated_lines(), query.timestamps());
+
+        if let Some(report) = related.replaced_report(path, &res) {
+            eprintln!("{}", report);
+        }
+
+        Ok(())
+    })?;
+    println!("{}", correlation);
+
+    partial.map_or(Ok(()), Err)
+}
+
+/// Execute a query and display the message templates found in its results.
+fn run_patterns(args: Args) -> Result<()> {
+    use log_cli::{Patterns, Query};
+
+    let query = Query::build(args.to_instructions()?)?;
+
+    if args.explain() {
+        println!("{}", query.pipeline());
+        return Ok(());
+    }
+
+    query.for_each_path(|i, path, res| {
+        print!("{}", query.path_header(i, path));
+        println!(
+            "{}",
+            Patterns::mine(res.enumerated_lines(), args.similarity())
+        );
+
+        if let Some(report) = query.replaced_report(path, &res) {
+            eprintln!("{}", report);
+        }
+
+        Ok(())
+    })
+}
+
+/// Execute a query and display the gaps and inversions found in the timestamps of its results.
+fn run_gaps(args: Args) -> Result<()> {
+    use log_cli::{Gaps, Query};
+
+    let query = Query::build(args.to_instructions()?)?;
+
+    if args.explain() {
+        println!("{}", query.pipeline());
+        return Ok(());
+    }
+
+    let threshold = args.gap();
+
+    query.for_each_path(|i, path, res| {
+        print!("{}", query.path_header(i, path));
+        println!(
+            "{}",
+            Gaps::find(res.enumerated_lines(), query.timestamps(), threshold)
+        );
+
+        if let Some(report) = query.replaced_report(path, &res) {
+            eprintln!("{}", report);
+        }
+
+        Ok(())
+    })
+}
+
+/// Execute a query and display the time buckets where the number of lines in its results spiked.
+fn run_spikes(args: Args) -> Result<()> {
+    use log_cli::{Query, Spikes};
+
+    let query = Query::build(args.to_instructions()?)?;
+
+    if args.explain() {
+        println!("{}", query.pipeline());
+        return Ok(());
+    }
+
+    let rule = args.spike_rule();
+
+    query.for_each_path(|i, path, res| {
+        print!("{}", query.path_header(i, path));
+        println!(
+            "{}",
+            Spikes::find(res.enumerated_lines(), query.timestamps(), rule)
+        );
+
+        if let Some(report) = query.replaced_report(path, &res) {
+            eprintln!("{}", report);
+        }
+
+        Ok(())
+    })
+}
+
+/// Open the interactive pager.
+fn run_view(args: Args) -> Result<()> {
+    use crate::{app, view::View};
+
+    args.reject(&app::VIEW_REJECTED_ARGS, "view")?;
+    View::open(args.to_instructions()?)?.run()
+}
+
+/// Build and write sidecar indexes for each path.
+fn run_index(args: Args) -> Result<()> {
+    use log_cli::Index;
+
+    for path in args.paths()? {
+        let index = Index::build(&path).map_err(|e| e.with_path(&path))?;
+        let sidecar = index.write(&path)?;
+
+        println!(
+            "Indexed {}: {} lines in {} blocks ({})",
+            path.display(),
+            index.len(),
+            index.block_count(),
+            sidecar.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Write a redacted copy of each path.
+fn run_redact(args: Args) -> Result<()> {
+    use log_cli::redact::{self, Redactor, Replacement};
+
+    let redactor = args
+        .redactor()?
+        .unwrap_or_else(|| Redactor::new(Replacement::Mask));
+    let dir = args.output_dir();
+
+    if let Some(dir) = &dir {
+        std::fs::create_dir_all(dir).map_err(|e| Error::from(e).with_path(dir))?;
+    }
+
+    let paths = args.paths()?;
+    let outputs: Vec<PathBuf> = paths
+        .iter()
+        .map(|path| redact::copy_path(path, dir.as_deref()))
+        .collect();
+
+    // Copies are checked before any is written, so one can't overwrite another.
+    for (i, output) in outputs.iter().enumerate() {
+        if let Some(j) = outputs[..i].iter().position(|o| o == output) {
+            return Err(Error::Args(format!(
+                "{} and {} would both be copied to {}",
+                paths[j].display(),
+                paths[i].display(),
+                output.display()
+            )));
+        }
+    }
+
+    for (path, output) in paths.iter().zip(outputs) {
+        let redacted = redactor
+            .write_copy(path, &output, args.encoding())
+            .map_err(|e| e.with_path(path))?;
+
+        println!("{}", redacted);
+    }
+
+    Ok(())
+}
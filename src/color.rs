@@ -0,0 +1,53 @@
+use crate::fields::{self, Level};
+use crossterm::style::{Color, Stylize};
+use std::borrow::Cow;
+
+/// Colours of lines by level, unless a config sets others.
+const DEFAULT_COLORS: [(Level, Color); 5] = [
+    (Level::Critical, Color::Red),
+    (Level::Error, Color::Red),
+    (Level::Warning, Color::Yellow),
+    (Level::Debug, Color::DarkGrey),
+    (Level::Trace, Color::DarkGrey),
+];
+
+/// Colours of displayed lines by their level.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    colors: Vec<(Level, Color)>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            colors: DEFAULT_COLORS.to_vec(),
+        }
+    }
+}
+
+impl Palette {
+    /// Set the colour of a level and return a new `Palette`.
+    pub fn with_color(self, level: Level, color: Color) -> Palette {
+        let mut palette = self;
+
+        palette.colors.retain(|(l, _)| *l != level);
+        palette.colors.push((level, color));
+
+        palette
+    }
+
+    /// Colour a line by its level. Lines without a level, or of a level without a colour, are
+    /// left as they are.
+    pub(crate) fn paint<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let color = fields::level(line).and_then(|level| {
+            self.colors
+                .iter()
+                .find_map(|(l, color)| (*l == level).then_some(*color))
+        });
+
+        match color {
+            Some(color) => Cow::Owned(line.with(color).to_string()),
+            None => Cow::Borrowed(line),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crossterm::style::Color;
+use log_cli::{Error, Level, Palette, Result};
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use toml::Value;
+
+/// Name of project-local config files, found in the working directory or its parents.
+const LOCAL_FILE: &str = ".log-cli.toml";
+
+/// Path of the user config file in the user's config directory.
+const USER_FILE: &str = "log-cli/config.toml";
+
+/// Values of arguments set in a config, by argument name.
+pub(crate) type Settings = BTreeMap<String, Vec<String>>;
+
+/// Defaults for arguments, saved queries, timestamp formats and colours read from config files.
+/// A config file has a table of each:
+///
+/// ```toml
+/// [defaults]
+/// output-tz = "Europe/Paris"
+///
+/// [queries.payments-errors]
+/// keywords = ["payments", "[error]"]
+///
+/// [formats]
+/// nginx = "%d/%b/%Y:%H:%M:%S %z"
+///
+/// [colors]
+/// info = "green"
+/// ```
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Config {
+    defaults: Settings,
+    queries: BTreeMap<String, Settings>,
+    formats: BTreeMap<String, String>,
+    colors: Vec<(Level, Color)>,
+}
+
+impl Config {
+    /// Load the user config file, then the project-local config file over it. Missing files are
+    /// skipped.
+    pub(crate) fn load() -> Result<Config> {
+        let mut config = Config::default();
+
+        for path in [user_path(), local_path()].into_iter().flatten() {
+            if path.is_file() {
+                let text =
+                    fs::read_to_string(&path).map_err(|e| Error::from(e).with_path(&path))?;
+
+                config = config.merge(Config::parse(&text, &path)?);
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Parse the text of a config file read from `path`.
+    pub(crate) fn parse(text: &str, path: &Path) -> Result<Config> {
+        let invalid = |msg: String| Error::Format(format!("{}: {}", path.display(), msg));
+        let value: Value = text.parse().map_err(|e| invalid(format!("{}", e)))?;
+        let mut config = Config::default();
+
+        for (name, value) in table(&value, name_of(path)).map_err(invalid)? {
+            match name.as_str() {
+                "defaults" => config.defaults = settings(value, name).map_err(invalid)?,
+                "queries" => {
+                    for (query, value) in table(value, name).map_err(invalid)? {
+                        let settings = settings(value, query).map_err(invalid)?;
+
+                        config.queries.insert(query.to_string(), settings);
+                    }
+                }
+                "formats" => {
+                    for (format, value) in table(value, name).map_err(invalid)? {
+                        let value = value.as_str().ok_or_else(|| {
+                            invalid(format!("format {} must be a string", format))
+                        })?;
+
+                        config.formats.insert(format.to_string(), value.to_string());
+                    }
+                }
+                "colors" => {
+                    for (level, value) in table(value, name).map_err(invalid)? {
+                        let color = value
+                            .as_str()
+                            .and_then(|color| Color::try_from(color).ok())
+                            .ok_or_else(|| invalid(format!("invalid color for {}", level)))?;
+                        let level = Level::parse(level)
+                            .ok_or_else(|| invalid(format!("unknown level {}", level)))?;
+
+                        config.colors.push((level, color));
+                    }
+                }
+                name => return Err(invalid(format!("unknown table {}", name))),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Merge a config over this one. Values it sets replace the values set here, and saved
+    /// queries it defines replace queries of the same name.
+    fn merge(self, other: Config) -> Config {
+        let mut config = self;
+
+        config.defaults.extend(other.defaults);
+        config.queries.extend(other.queries);
+        config.formats.extend(other.formats);
+        config.colors.extend(other.colors);
+
+        config
+    }
+
+    /// Select a saved query, setting its values over the defaults.
+    pub(crate) fn select(self, name: &str) -> Result<Config> {
+        let mut config = self;
+        let settings = config.queries.get(name).cloned().ok_or_else(|| {
+            Error::Args(format!("no saved query named {} was found in config", name))
+        })?;
+
+        config.defaults.extend(settings);
+
+        Ok(config)
+    }
+
+    /// Remove the value set for an argument and return a new `Config`.
+    pub(crate) fn without(self, id: &str) -> Config {
+        let mut config = self;
+
+        config.defaults.remove(id);
+
+        config
+    }
+
+    /// Access the values set for arguments.
+    pub(crate) fn defaults(&self) -> &Settings {
+        &self.defaults
+    }
+
+    /// Access timestamp formats by name.
+    pub(crate) fn formats(&self) -> &BTreeMap<String, String> {
+        &self.formats
+    }
+
+    /// Get the colours of lines by level, with the colours set over the default colours.
+    pub(crate) fn palette(&self) -> Palette {
+        self.colors
+            .iter()
+            .fold(Palette::default(), |palette, (level, color)| {
+                palette.with_color(*level, *color)
+            })
+    }
+}
+
+/// Path of the user config file, in `$XDG_CONFIG_HOME` or `~/.config`.
+fn user_path() -> Option<PathBuf> {
+    let dir = match env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(dir.join(USER_FILE))
+}
+
+/// Path of the nearest project-local config file, in the working directory or its parents.
+fn local_path() -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+
+    cwd.ancestors()
+        .map(|dir| dir.join(LOCAL_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Name of a config file for messages.
+fn name_of(path: &Path) -> &str {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("config")
+}
+
+/// Read a value as a table.
+fn table<'a>(value: &'a Value, name: &str) -> std::result::Result<&'a toml::value::Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("{} must be a table", name))
+}
+
+/// Read a table of argument values. Names are argument names, and underscores can be used for
+/// dashes. Flags are set with booleans, and arguments taking several values with arrays.
+fn settings(value: &Value, name: &str) -> std::result::Result<Settings, String> {
+    let mut res = Settings::new();
+
+    for (key, value) in table(value, name)? {
+        let values = match value {
+            Value::Array(values) => values.iter().map(scalar).collect::<Option<Vec<_>>>(),
+            value => scalar(value).map(|value| vec![value]),
+        };
+        let values = values.ok_or_else(|| format!("invalid value for {} in {}", key, name))?;
+
+        res.insert(key.replace('_', "-"), values);
+    }
+
+    Ok(res)
+}
+
+/// Read a single value as an argument value.
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.to_string()),
+        Value::Integer(n) => Some(n.to_string()),
+        Value::Float(n) => Some(n.to_string()),
+        Value::Boolean(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: &str = r#"
+[defaults]
+output_tz = "utc"
+context = 2
+dedupe = true
+
+[queries.payments-errors]
+keywords = ["payments", "[error]"]
+context = 5
+
+[formats]
+nginx = "%d/%b/%Y:%H:%M:%S %z"
+
+[colors]
+info = "green"
+"#;
+
+    const LOCAL: &str = r#"
+[defaults]
+context = 1
+
+[queries.payments-errors]
+keywords = ["payments"]
+"#;
+
+    fn values(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_and_merge_configs() {
+        let user = Config::parse(USER, Path::new("config.toml")).unwrap();
+        let local = Config::parse(LOCAL, Path::new(".log-cli.toml")).unwrap();
+
+        assert_eq!(user.defaults()["output-tz"], values(&["utc"]));
+        assert_eq!(user.defaults()["dedupe"], values(&["true"]));
+        assert_eq!(user.formats()["nginx"], "%d/%b/%Y:%H:%M:%S %z");
+        assert_ne!(user.palette(), Palette::default());
+
+        let selected = user.clone().select("payments-errors").unwrap();
+        assert_eq!(selected.defaults()["context"], values(&["5"]));
+        assert_eq!(
+            selected.defaults()["keywords"],
+            values(&["payments", "[error]"])
+        );
+
+        let merged = user.merge(local);
+        assert_eq!(merged.defaults()["context"], values(&["1"]));
+        assert_eq!(merged.defaults()["dedupe"], values(&["true"]));
+
+        let selected = merged.select("payments-errors").unwrap();
+        assert_eq!(selected.defaults()["keywords"], values(&["payments"]));
+        assert_eq!(selected.defaults()["context"], values(&["1"]));
+        assert!(selected.select("missing").is_err());
+    }
+
+    #[test]
+    fn reject_invalid_configs() {
+        let path = Path::new("config.toml");
+
+        assert!(Config::parse("[colors]\ninfo = \"chartreuse\"", path).is_err());
+        assert!(Config::parse("[colors]\nloud = \"red\"", path).is_err());
+        assert!(Config::parse("[aliases]\na = 1", path).is_err());
+        assert!(Config::parse("[defaults]\ncontext = { n = 1 }", path).is_err());
+        assert!(Config::parse("defaults = 1", path).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{fields, lines::EnumeratedLines, timestamp::Parser};
+use chrono::NaiveDateTime;
+use regex::Regex;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// How IDs are read from lines.
+#[derive(Clone, Debug)]
+pub enum IdPattern {
+    /// The value of a `key=value` field.
+    Field(String),
+    /// The first capture group of a regex, or the whole match if it has no groups.
+    Regex(Regex),
+}
+
+impl IdPattern {
+    /// Parse an ID pattern. Field names (ex: request_id) are read as fields, and anything else is
+    /// compiled as a regex.
+    pub fn parse(value: &str) -> Result<IdPattern, regex::Error> {
+        if fields::is_field_name(value) {
+            return Ok(IdPattern::Field(value.to_string()));
+        }
+
+        Regex::new(value).map(IdPattern::Regex)
+    }
+
+    /// Find the ID of a line. Only the first ID in a line is read.
+    pub(crate) fn find<'a>(&self, line: &'a str) -> Option<&'a str> {
+        let id = match self {
+            IdPattern::Field(name) => fields::field(line, name)?,
+            IdPattern::Regex(re) => {
+                let captures = re.captures(line)?;
+
+                captures.get(1).or_else(|| captures.get(0))?.as_str()
+            }
+        };
+
+        (!id.is_empty()).then_some(id)
+    }
+}
+
+impl fmt::Display for IdPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IdPattern::Field(name) => write!(f, "field {}", name),
+            IdPattern::Regex(re) => write!(f, "regex {}", re),
+        }
+    }
+}
+
+/// A line sharing an ID, with the position of the path it was read from and its UTC timestamp.
+struct Related {
+    path: usize,
+    ln: usize,
+    line: String,
+    instant: Option<NaiveDateTime>,
+}
+
+/// Lines across paths grouped by the IDs they share. IDs are kept in the order they were first
+/// found.
+pub struct Correlation {
+    pattern: IdPattern,
+    ids: Vec<String>,
+    groups: HashMap<String, Vec<Related>>,
+    paths: Vec<PathBuf>,
+}
+
+impl Correlation {
+    /// Create an empty `Correlation` reading IDs with `pattern`.
+    pub fn new(pattern: IdPattern) -> Correlation {
+        Correlation {
+            pattern,
+            ids: vec![],
+            groups: HashMap::new(),
+            paths: vec![],
+        }
+    }
+
+    /// Check if no IDs were found.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Add the IDs of lines to correlate.
+    pub fn add_ids(&mut self, lines: &EnumeratedLines) {
+        for (_, line) in lines.iter() {
+            if let Some(id) = self.pattern.find(line) {
+                if !self.groups.contains_key(id) {
+                    self.ids.push(id.to_string());
+                    self.groups.insert(id.to_string(), vec![]);
+                }
+            }
+        }
+    }
+
+    /// Add the lines of a path sharing one of the IDs.
+    pub fn add_lines(&mut self, path: &Path, lines: &EnumeratedLines, timestamps: &Parser) {
+        let position = self.paths.len();
+        self.paths.push(path.to_path_buf());
+
+        for (ln, line) in lines.iter() {
+            let group = match self
+                .pattern
+                .find(line)
+                .and_then(|id| self.groups.get_mut(id))
+            {
+                Some(group) => group,
+                None => continue,
+            };
+
+            group.push(Related {
+                path: position,
+                ln: *ln,
+                line: line.clone(),
+                instant: timestamps.instant(line),
+            });
+        }
+    }
+
+    /// Lines sharing an ID ordered by time. Lines without a timestamp come last, and lines logged
+    /// at the same time keep their path order.
+    fn group(&self, id: &str) -> Vec<&Related> {
+        let mut group: Vec<&Related> = self.groups.get(id).into_iter().flatten().collect();
+
+        group.sort_by(|a, b| match (a.instant, b.instant) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+
+        group
+    }
+}
+
+impl fmt::Display for Correlation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let related = self.groups.values().map(Vec::len).sum::<usize>();
+        let pad_len = self
+            .groups
+            .values()
+            .flatten()
+            .map(|r| r.ln.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        write!(f, "")?;
+
+        for id in self.ids.iter() {
+            let group = self.group(id);
+
+            write!(f, "\n{}: {}", id, describe(group.len(), "line"))?;
+
+            for r in group {
+                write!(
+                    f,
+                    "\n  {} ln{:0pad_len$} {}",
+                    self.paths[r.path].display(),
+                    r.ln,
+                    r.line,
+                    pad_len = pad_len
+                )?;
+            }
+        }
+
+        write!(
+            f,
+            "\nFound {} sharing {} by {} across {}.",
+            describe(related, "line"),
+            describe(self.ids.len(), "ID"),
+            self.pattern,
+            describe(self.paths.len(), "file")
+        )
+    }
+}
+
+/// Describe a count of things.
+fn describe(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> EnumeratedLines {
+        lines.iter().map(|l| l.to_string()).enumerate().collect()
+    }
+
+    #[test]
+    fn parse_id_patterns() {
+        let line = "2022-01-01 07:00:00,0 [error] request_id=abc-1 trace=[t-9] failed";
+
+        assert!(matches!(
+            IdPattern::parse("request_id"),
+            Ok(IdPattern::Field(_))
+        ));
+        assert_eq!(
+            IdPattern::parse("request_id").unwrap().find(line),
+            Some("abc-1")
+        );
+        assert_eq!(
+            IdPattern::parse(r"trace=\[(\S+)\]").unwrap().find(line),
+            Some("t-9")
+        );
+        assert_eq!(IdPattern::parse(r"t-\d").unwrap().find(line), Some("t-9"));
+        assert_eq!(IdPattern::parse("user").unwrap().find(line), None);
+        assert!(IdPattern::parse("(").is_err());
+    }
+
+    #[test]
+    fn correlate_lines_across_paths() {
+        let api = lines(&[
+            "2022-01-01 07:00:01,0 [info] request_id=a received",
+            "2022-01-01 07:00:02,0 [info] request_id=b received",
+            "2022-01-01 07:00:05,0 [error] request_id=a failed",
+        ]);
+        let db = lines(&[
+            "2022-01-01 07:00:03,0 [debug] request_id=a query",
+            "2022-01-01 07:00:04,0 [debug] request_id=b query",
+            "no timestamp request_id=a",
+        ]);
+
+        let mut correlation = Correlation::new(IdPattern::parse("request_id").unwrap());
+        correlation.add_ids(&lines(&[
+            "2022-01-01 07:00:05,0 [error] request_id=a failed",
+        ]));
+        correlation.add_lines(Path::new("api.log"), &api, &Parser::default());
+        correlation.add_lines(Path::new("db.log"), &db, &Parser::default());
+
+        let group: Vec<(usize, usize)> = correlation
+            .group("a")
+            .iter()
+            .map(|r| (r.path, r.ln))
+            .collect();
+
+        assert_eq!(group, vec![(0, 0), (1, 0), (0, 2), (1, 2)]);
+        assert!(correlation.group("b").is_empty());
+        assert!(correlation
+            .to_string()
+            .ends_with("Found 4 lines sharing 1 ID by field request_id across 2 files."));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{buffer, lines::Lines, Result};
+use std::{
+    borrow::Cow,
+    char::{self, REPLACEMENT_CHARACTER},
+    fs::{self, File},
+    io::Read,
+    path::Path,
+};
+
+/// Characters decoded from bytes 0x80 through 0x9f by Windows-1252. Bytes the code page leaves
+/// undefined map to the C1 control with the same value, as browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+/// Text encodings log files can be decoded from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    /// Parse an encoding name. Names are case insensitive and `utf-16` is little-endian unless
+    /// the file has a byte order mark.
+    pub fn parse(name: &str) -> Option<Encoding> {
+        match name.to_lowercase().replace('_', "-").as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "utf-16" | "utf16" | "utf-16le" | "utf16le" => Some(Encoding::Utf16Le),
+            "utf-16be" | "utf16be" => Some(Encoding::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Some(Encoding::Latin1),
+            "windows-1252" | "cp1252" => Some(Encoding::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Determine the encoding of a file. A UTF-16 byte order mark takes precedence over the
+    /// encoding given, and files without one default to UTF-8.
+    pub(crate) fn detect<P>(path: P, given: Option<Encoding>) -> Result<Encoding>
+    where
+        P: AsRef<Path>,
+    {
+        let mut bom = [0; 2];
+        let n = File::open(path)?.take(2).read(&mut bom)?;
+
+        Ok(Encoding::detect_bytes(&bom[..n], given))
+    }
+
+    /// Determine the encoding of bytes read from the start of a file.
+    pub(crate) fn detect_bytes(bytes: &[u8], given: Option<Encoding>) -> Encoding {
+        match bytes {
+            [0xff, 0xfe, ..] => Encoding::Utf16Le,
+            [0xfe, 0xff, ..] => Encoding::Utf16Be,
+            _ => given.unwrap_or(Encoding::Utf8),
+        }
+    }
+
+    /// Check if lines are separated by single `\n` bytes, so files can be split into lines
+    /// without decoding them.
+    pub fn is_byte_oriented(&self) -> bool {
+        !matches!(self, Encoding::Utf16Le | Encoding::Utf16Be)
+    }
+
+    /// Decode a line. Return the text and whether any bytes were invalid and replaced with
+    /// U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> (String, bool) {
+        match self {
+            Encoding::Utf8 => match String::from_utf8_lossy(bytes) {
+                Cow::Borrowed(s) => (s.to_string(), false),
+                Cow::Owned(s) => (s, true),
+            },
+            Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+            Encoding::Latin1 => (bytes.iter().map(|b| *b as char).collect(), false),
+            Encoding::Windows1252 => (
+                bytes
+                    .iter()
+                    .map(|b| match b {
+                        0x80..=0x9f => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                        _ => *b as char,
+                    })
+                    .collect(),
+                false,
+            ),
+        }
+    }
+
+    /// Read and decode every line of a file. Lines are split on the encoding's line feed, so
+    /// this is used for encodings that aren't byte oriented.
+    pub(crate) fn read_lines<P>(&self, path: P) -> Result<Lines>
+    where
+        P: AsRef<Path>,
+    {
+        let lines = match buffer::map_file(&path)? {
+            Some(map) => self.decode_lines(&map),
+            None => self.decode_lines(&fs::read(&path)?),
+        };
+
+        Ok(lines)
+    }
+
+    /// Split a buffer into decoded lines without line endings or a byte order mark.
+    pub(crate) fn decode_lines(&self, bytes: &[u8]) -> Lines {
+        let (width, newline, cr): (usize, &[u8], &[u8]) = match self {
+            Encoding::Utf16Le => (2, b"\n\0", b"\r\0"),
+            Encoding::Utf16Be => (2, b"\0\n", b"\0\r"),
+            _ => (1, b"\n", b"\r"),
+        };
+        let bytes = match (self, bytes) {
+            (Encoding::Utf16Le, [0xff, 0xfe, rest @ ..])
+            | (Encoding::Utf16Be, [0xfe, 0xff, rest @ ..]) => rest,
+            _ => bytes,
+        };
+
+        let mut lines = vec![];
+        let mut replaced = vec![];
+        let mut push = |line: &[u8]| {
+            let line = line.strip_suffix(cr).unwrap_or(line);
+            let (text, invalid) = self.decode(line);
+
+            if invalid {
+                replaced.push(lines.len());
+            }
+            lines.push((lines.len(), text));
+        };
+
+        // Line feeds are only matched on code unit boundaries.
+        let mut start = 0;
+        for i in (0..bytes.len() / width).map(|i| i * width) {
+            if &bytes[i..i + width] == newline {
+                push(&bytes[start..i]);
+                start = i + width;
+            }
+        }
+
+        // A final line without a line ending is still a line.
+        if start < bytes.len() {
+            push(&bytes[start..]);
+        }
+
+        Lines::new(lines).with_replaced(replaced)
+    }
+}
+
+/// Decode UTF-16 code units read by `unit`. Unpaired surrogates and a trailing odd byte are
+/// replaced.
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16) -> (String, bool) {
+    let units = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+    let odd = !bytes.len().is_multiple_of(2);
+    let mut invalid = odd;
+
+    let mut text: String = char::decode_utf16(units)
+        .map(|c| {
+            c.unwrap_or_else(|_| {
+                invalid = true;
+                REPLACEMENT_CHARACTER
+            })
+        })
+        .collect();
+
+    if odd {
+        text.push(REPLACEMENT_CHARACTER);
+    }
+
+    (text, invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn decode_single_byte_encodings() {
+        assert_eq!(
+            Encoding::Latin1.decode(b"caf\xe9"),
+            ("café".to_string(), false)
+        );
+        assert_eq!(
+            Encoding::Windows1252.decode(b"\x93quoted\x94 \x80"),
+            ("\u{201c}quoted\u{201d} €".to_string(), false)
+        );
+        assert_eq!(
+            Encoding::Utf8.decode(b"bad \xff byte"),
+            ("bad \u{fffd} byte".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn decode_utf16_lines() {
+        let mut bytes = vec![0xff, 0xfe];
+        bytes.extend(utf16le("first\r\nsecond \u{0a0a}\n"));
+        // An unpaired surrogate.
+        bytes.extend([0x00, 0xd8]);
+        bytes.extend(utf16le(" third"));
+
+        let lines = Encoding::Utf16Le.decode_lines(&bytes);
+
+        assert_eq!(
+            lines.enumerated_lines(),
+            &vec![
+                (0, "first".to_string()),
+                (1, "second \u{0a0a}".to_string()),
+                (2, "\u{fffd} third".to_string())
+            ]
+        );
+        assert_eq!(lines.replaced(), &[2]);
+        assert_eq!(Encoding::parse("UTF-16BE"), Some(Encoding::Utf16Be));
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Errors that can end a `log-cli` run.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed. The path is attached once it's known.
+    Io {
+        /// Path of the file or source being read or written, if known.
+        path: Option<PathBuf>,
+        /// The underlying IO error.
+        source: io::Error,
+    },
+    /// Arguments are missing or can't be used together.
+    Args(String),
+    /// A value passed couldn't be parsed.
+    Parse(String),
+    /// A file isn't in a format that can be read.
+    Format(String),
+    /// The query didn't match any lines.
+    NoMatch,
+    /// Some paths of a query failed. Each failure was reported as it happened.
+    Partial {
+        /// Number of paths that couldn't be read.
+        failed: usize,
+        /// Number of paths in the query. The `log-cli` binary exits with code 3 if some paths
+        /// were read (`failed < total`) and 2 if every path failed.
+        total: usize,
+    },
+}
+
+impl Error {
+    /// Attach a path to an IO error that doesn't have one yet.
+    pub fn with_path(self, path: &Path) -> Error {
+        match self {
+            Error::Io { path: None, source } => Error::Io {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io {
+                path: Some(path),
+                source,
+            } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::Args(msg) | Error::Parse(msg) | Error::Format(msg) => write!(f, "{}", msg),
+            Error::NoMatch => write!(f, "no lines matched"),
+            Error::Partial { failed, total } => write!(
+                f,
+                "{} of {} paths read, {} failed",
+                total - failed,
+                total,
+                failed
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::Io { path: None, source }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attach_path_to_io_errors() {
+        let e = Error::from(io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(
+            e.with_path(Path::new("app.log")).to_string(),
+            "app.log: not found"
+        );
+
+        let e = Error::Args("no paths were found".to_string());
+        assert_eq!(
+            e.with_path(Path::new("app.log")).to_string(),
+            "no paths were found"
+        );
+    }
+}
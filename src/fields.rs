@@ -0,0 +1,85 @@
+/// Severity levels of log lines, ordered least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Critical,
+}
+
+impl Level {
+    /// Parse a level name (ex: warn, WARNING or [warning]).
+    pub fn parse(name: &str) -> Option<Level> {
+        let name = name.trim_matches(|c: char| !c.is_ascii_alphabetic());
+
+        match name.to_ascii_lowercase().as_str() {
+            "trace" => Some(Level::Trace),
+            "debug" => Some(Level::Debug),
+            "info" => Some(Level::Info),
+            "notice" => Some(Level::Notice),
+            "warn" | "warning" => Some(Level::Warning),
+            "error" | "err" => Some(Level::Error),
+            "critical" | "crit" | "fatal" | "alert" | "emerg" => Some(Level::Critical),
+            _ => None,
+        }
+    }
+}
+
+/// Tokens searched for a level from the start of a line.
+const LEVEL_TOKENS: usize = 6;
+
+/// Find the level of a line. Levels are read from a `level=` field, or from one of the first
+/// tokens of the line (ex: [info] or ERROR:).
+pub(crate) fn level(line: &str) -> Option<Level> {
+    if let Some(level) = field(line, "level").and_then(Level::parse) {
+        return Some(level);
+    }
+
+    line.split_whitespace()
+        .take(LEVEL_TOKENS)
+        .find_map(|token| match token.contains('=') {
+            true => None,
+            false => Level::parse(token),
+        })
+}
+
+/// Check if a name can be the key of a field (ex: user_id, http.status or trace-id).
+pub(crate) fn is_field_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || ['_', '-', '.'].contains(&c))
+}
+
+/// Find the value of a `key=value` field in a line. Quotes around the value are removed.
+pub(crate) fn field<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    line.split_whitespace().find_map(|token| {
+        let value = token.strip_prefix(key)?.strip_prefix('=')?;
+        let value = value.trim_end_matches(',');
+
+        Some(value.trim_matches(['"', '\'']))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_levels_and_fields() {
+        let line = "2022-01-01 07:00:00,0 [warning] module1 user=42 path=\"/a\", took=1.5";
+
+        assert_eq!(level(line), Some(Level::Warning));
+        assert_eq!(level("ts=1 level=ERROR msg=failed"), Some(Level::Error));
+        assert_eq!(level("2022-01-01 started"), None);
+        assert!(Level::Debug < Level::Critical);
+
+        assert_eq!(field(line, "user"), Some("42"));
+        assert_eq!(field(line, "path"), Some("/a"));
+        assert_eq!(field(line, "took"), Some("1.5"));
+        assert_eq!(field(line, "use"), None);
+    }
+}
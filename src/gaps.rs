@@ -0,0 +1,173 @@
+use crate::{
+    lines::EnumeratedLines,
+    timestamp::{self, Parser},
+};
+use chrono::{Duration, NaiveDateTime};
+use std::fmt;
+
+/// Ways the timestamps of consecutive lines can break.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BreakKind {
+    /// No lines were logged for longer than the threshold.
+    Gap,
+    /// A timestamp is earlier than the one before it.
+    Inversion,
+}
+
+/// A break between two consecutive timestamped lines.
+pub(crate) struct Break {
+    kind: BreakKind,
+    /// Time between the lines, negative for inversions.
+    duration: Duration,
+    /// Line numbers and timestamps, as logged, of the lines before and after the break.
+    before: (usize, NaiveDateTime),
+    after: (usize, NaiveDateTime),
+}
+
+/// Gaps and inversions found in the timestamps of lines. Lines without a timestamp are skipped,
+/// so breaks are found between the timestamped lines around them.
+pub struct Gaps {
+    threshold: Duration,
+    breaks: Vec<Break>,
+    timestamped: usize,
+}
+
+impl Gaps {
+    /// Find gaps longer than `threshold`, and inversions, in enumerated lines. Timestamps are
+    /// compared in UTC.
+    pub fn find(lines: &EnumeratedLines, timestamps: &Parser, threshold: Duration) -> Gaps {
+        let mut breaks = vec![];
+        let mut previous: Option<(usize, &str, NaiveDateTime)> = None;
+        let mut timestamped = 0;
+
+        for (ln, line) in lines.iter() {
+            let t = match timestamps.instant(line) {
+                Some(t) => t,
+                None => continue,
+            };
+            timestamped += 1;
+
+            if let Some((prev_ln, prev_line, prev_t)) = previous {
+                let duration = t - prev_t;
+                let kind = match duration {
+                    d if d < Duration::zero() => Some(BreakKind::Inversion),
+                    d if d > threshold => Some(BreakKind::Gap),
+                    _ => None,
+                };
+
+                // Breaks are displayed with the timestamps as logged.
+                if let Some(kind) = kind {
+                    breaks.push(Break {
+                        kind,
+                        duration,
+                        before: (prev_ln, timestamps.datetime(prev_line).unwrap_or(prev_t)),
+                        after: (*ln, timestamps.datetime(line).unwrap_or(t)),
+                    });
+                }
+            }
+
+            previous = Some((*ln, line, t));
+        }
+
+        Gaps {
+            threshold,
+            breaks,
+            timestamped,
+        }
+    }
+
+    /// Count the breaks of a kind.
+    fn count(&self, kind: BreakKind) -> usize {
+        self.breaks.iter().filter(|b| b.kind == kind).count()
+    }
+}
+
+impl fmt::Display for Gaps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Pad durations and line numbers to the widest found.
+        let durations: Vec<String> = self
+            .breaks
+            .iter()
+            .map(|b| timestamp::describe_duration(b.duration))
+            .collect();
+        let duration_len = durations.iter().map(String::len).max().unwrap_or(0);
+        let pad_len = self
+            .breaks
+            .iter()
+            .map(|b| b.before.0.max(b.after.0).to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        write!(f, "")?;
+
+        for (b, duration) in self.breaks.iter().zip(durations) {
+            let kind = match b.kind {
+                BreakKind::Gap => "gap",
+                BreakKind::Inversion => "inversion",
+            };
+
+            write!(
+                f,
+                "\n{:<9} {:<duration_len$} ln{:0pad_len$}-ln{:0pad_len$} {} to {}",
+                kind,
+                duration,
+                b.before.0,
+                b.after.0,
+                b.before.1,
+                b.after.1,
+                duration_len = duration_len,
+                pad_len = pad_len
+            )?;
+        }
+
+        write!(
+            f,
+            "\nFound {} over {} and {} in {}.",
+            describe(self.count(BreakKind::Gap), "gap"),
+            timestamp::describe_duration(self.threshold),
+            describe(self.count(BreakKind::Inversion), "inversion"),
+            describe(self.timestamped, "timestamped line")
+        )
+    }
+}
+
+/// Describe a count of things.
+fn describe(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_gaps_and_inversions() {
+        let lines: EnumeratedLines = [
+            "2022-01-01 07:00:00,0 started",
+            "2022-01-01 07:01:00,0 working",
+            "  continued without a timestamp",
+            "2022-01-01 07:20:00,0 resumed",
+            "2022-01-01 07:19:58,0 late write",
+            "2022-01-01 07:21:00,0 done",
+        ]
+        .iter()
+        .map(|l| l.to_string())
+        .enumerate()
+        .collect();
+
+        let gaps = Gaps::find(&lines, &Parser::default(), Duration::minutes(5));
+        let breaks = &gaps.breaks;
+
+        assert_eq!(breaks.len(), 2);
+        assert_eq!(breaks[0].kind, BreakKind::Gap);
+        assert_eq!((breaks[0].before.0, breaks[0].after.0), (1, 3));
+        assert_eq!(breaks[1].kind, BreakKind::Inversion);
+        assert_eq!(breaks[1].duration, Duration::seconds(-2));
+        assert!(gaps
+            .to_string()
+            .ends_with("Found 1 gap over 5m and 1 inversion in 5 timestamped lines."));
+    }
+}
@@ -0,0 +1,410 @@
+use crate::{buffer, timestamp::Parser, Result};
+use chrono::NaiveDateTime;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufWriter, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Lines in each indexed block.
+const BLOCK_LINES: usize = 4096;
+
+/// Bytes in each block's bloom filter.
+const BLOOM_BYTES: usize = 4096;
+
+/// Bit positions set in a bloom filter for each token.
+const BLOOM_HASHES: u64 = 3;
+
+/// Bytes in each token added to a bloom filter. Lines are split into overlapping trigrams so
+/// keywords can be checked as substrings rather than whole words.
+const TOKEN_LEN: usize = 3;
+
+/// Bytes identifying an index file and its version.
+const MAGIC: &[u8; 8] = b"LOGCLIX2";
+
+/// Extension appended to a log file's name for its sidecar index.
+const EXTENSION: &str = "lcidx";
+
+/// Timestamp stored for blocks without timestamped lines.
+const NO_TIMESTAMP: i64 = i64::MIN;
+
+/// A block of consecutive lines in an indexed file.
+pub(crate) struct Block {
+    offset: u64,
+    first_line: usize,
+    len: usize,
+    min_timestamp: i64,
+    max_timestamp: i64,
+    bloom: Vec<u8>,
+}
+
+impl Block {
+    /// Create an empty `Block` starting at a byte offset and line number.
+    fn new(offset: u64, first_line: usize) -> Block {
+        Block {
+            offset,
+            first_line,
+            len: 0,
+            min_timestamp: NO_TIMESTAMP,
+            max_timestamp: NO_TIMESTAMP,
+            bloom: vec![0; BLOOM_BYTES],
+        }
+    }
+
+    /// Add a line's timestamp and tokens to the block.
+    fn add_line(&mut self, line: &str) {
+        if let Some(t) = Parser::default().instant(line) {
+            let t = t.timestamp_millis();
+
+            if self.min_timestamp == NO_TIMESTAMP {
+                self.min_timestamp = t;
+                self.max_timestamp = t;
+            } else {
+                self.min_timestamp = self.min_timestamp.min(t);
+                self.max_timestamp = self.max_timestamp.max(t);
+            }
+        }
+
+        for token in line.as_bytes().windows(TOKEN_LEN) {
+            for bit in bloom_bits(token) {
+                self.bloom[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+
+        self.len += 1;
+    }
+
+    /// Byte offset of the block's first line.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Line numbers in the block.
+    pub(crate) fn lines(&self) -> Range<usize> {
+        self.first_line..self.first_line + self.len
+    }
+
+    /// Check if the block has lines within an inclusive range of line numbers.
+    pub(crate) fn overlaps_lines(&self, lower: usize, upper: usize) -> bool {
+        self.len > 0 && self.first_line <= upper && self.first_line + self.len > lower
+    }
+
+    /// Check if the block has timestamped lines within an inclusive range of timestamps.
+    pub(crate) fn overlaps_time(&self, lower: &NaiveDateTime, upper: &NaiveDateTime) -> bool {
+        self.min_timestamp != NO_TIMESTAMP
+            && self.min_timestamp <= upper.timestamp_millis()
+            && self.max_timestamp >= lower.timestamp_millis()
+    }
+
+    /// Check if a keyword may be found in the block. Keywords shorter than a token can't be ruled
+    /// out.
+    pub(crate) fn may_contain(&self, keyword: &str) -> bool {
+        keyword
+            .as_bytes()
+            .windows(TOKEN_LEN)
+            .all(|token| bloom_bits(token).all(|bit| self.bloom[bit / 8] & (1 << (bit % 8)) != 0))
+    }
+}
+
+/// Sidecar index of a log file. Blocks of lines are indexed with their byte offset, timestamp
+/// bounds and a bloom filter of their tokens so queries can seek straight to the blocks that
+/// can contain selected lines.
+pub struct Index {
+    size: u64,
+    modified: (u64, u32),
+    len: usize,
+    blocks: Vec<Block>,
+}
+
+impl Index {
+    /// Path of the sidecar index for a log file.
+    pub(crate) fn sidecar_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(EXTENSION);
+
+        path.with_file_name(name)
+    }
+
+    /// Build an `Index` by scanning a log file.
+    pub fn build(path: &Path) -> Result<Index> {
+        let (size, modified) = file_version(path)?;
+        let mut reader = buffer::read_file(path)?;
+        let mut blocks: Vec<Block> = vec![];
+        let mut buf = vec![];
+        let mut offset = 0;
+        let mut len = 0;
+
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+
+            if n == 0 {
+                break;
+            }
+
+            if len % BLOCK_LINES == 0 {
+                blocks.push(Block::new(offset, len));
+            }
+
+            // Lines are indexed as they're matched, with only their line ending removed.
+            buffer::trim_line_ending(&mut buf);
+            let line = String::from_utf8_lossy(&buf);
+            blocks.last_mut().unwrap().add_line(&line);
+
+            offset += n as u64;
+            len += 1;
+        }
+
+        Ok(Index {
+            size,
+            modified,
+            len,
+            blocks,
+        })
+    }
+
+    /// Load the sidecar index for a log file. Return `None` if there is no index, it can't be
+    /// read or the log file has changed since it was indexed, so the file is read without it.
+    pub(crate) fn load(path: &Path) -> Option<Index> {
+        Index::read(path).ok().flatten()
+    }
+
+    /// Read and validate the sidecar index for a log file.
+    fn read(path: &Path) -> Result<Option<Index>> {
+        let mut reader = io::BufReader::new(File::open(Index::sidecar_path(path))?);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Ok(None);
+        }
+
+        let size = read_u64(&mut reader)?;
+        let modified = (read_u64(&mut reader)?, read_u64(&mut reader)? as u32);
+
+        if (size, modified) != file_version(path)? {
+            return Ok(None);
+        }
+
+        let len = read_u64(&mut reader)? as usize;
+        let mut blocks: Vec<Block> = vec![];
+
+        for _ in 0..read_u64(&mut reader)? {
+            let mut block = Block {
+                offset: read_u64(&mut reader)?,
+                first_line: read_u64(&mut reader)? as usize,
+                len: read_u64(&mut reader)? as usize,
+                min_timestamp: read_u64(&mut reader)? as i64,
+                max_timestamp: read_u64(&mut reader)? as i64,
+                bloom: vec![0; BLOOM_BYTES],
+            };
+            reader.read_exact(&mut block.bloom)?;
+
+            // Blocks must follow each other through the file.
+            let (offset, first_line) = blocks
+                .last()
+                .map_or((0, 0), |b| (b.offset + 1, b.first_line + b.len));
+            if block.offset < offset || block.offset > size || block.first_line != first_line {
+                return Ok(None);
+            }
+
+            blocks.push(block);
+        }
+
+        if blocks.last().map_or(0, |b| b.first_line + b.len) != len {
+            return Ok(None);
+        }
+
+        Ok(Some(Index {
+            size,
+            modified,
+            len,
+            blocks,
+        }))
+    }
+
+    /// Write the index next to its log file. The index is written to a temporary file first and
+    /// renamed into place, so an index is never left partly written. Return the path written to.
+    pub fn write(&self, path: &Path) -> Result<PathBuf> {
+        let sidecar = Index::sidecar_path(path);
+        let mut name = sidecar.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.tmp", std::process::id()));
+        let temp = sidecar.with_file_name(name);
+
+        let res = self
+            .write_to(&temp)
+            .and_then(|_| fs::rename(&temp, &sidecar).map_err(|e| e.into()));
+
+        if let Err(e) = res {
+            fs::remove_file(&temp).ok();
+            return Err(e.with_path(&sidecar));
+        }
+
+        Ok(sidecar)
+    }
+
+    /// Write the index to a path.
+    fn write_to(&self, sidecar: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(sidecar)?);
+
+        writer.write_all(MAGIC)?;
+        for n in [
+            self.size,
+            self.modified.0,
+            self.modified.1 as u64,
+            self.len as u64,
+            self.blocks.len() as u64,
+        ] {
+            writer.write_all(&n.to_le_bytes())?;
+        }
+
+        for block in self.blocks.iter() {
+            for n in [
+                block.offset,
+                block.first_line as u64,
+                block.len as u64,
+                block.min_timestamp as u64,
+                block.max_timestamp as u64,
+            ] {
+                writer.write_all(&n.to_le_bytes())?;
+            }
+            writer.write_all(&block.bloom)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Number of lines in the indexed file.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the indexed file has no lines.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of blocks the indexed file is split into.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Access the indexed blocks.
+    pub(crate) fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Access the last block starting at or before a byte offset.
+    pub(crate) fn block_at(&self, offset: u64) -> Option<&Block> {
+        let i = self.blocks.partition_point(|block| block.offset <= offset);
+
+        i.checked_sub(1).map(|i| &self.blocks[i])
+    }
+}
+
+/// Size and modification time of a file, used to check if an index is fresh.
+fn file_version(path: &Path) -> Result<(u64, (u64, u32))> {
+    let metadata = fs::metadata(path)?;
+    // Times before the epoch can't be told apart, but only need to change when the file does.
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    Ok((
+        metadata.len(),
+        (modified.as_secs(), modified.subsec_nanos()),
+    ))
+}
+
+/// Read a little-endian `u64`.
+fn read_u64<R>(reader: &mut R) -> io::Result<u64>
+where
+    R: Read,
+{
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Bloom filter bit positions for a token. Positions are derived from two halves of a stable
+/// FNV-1a hash so indexes can be read by later runs.
+fn bloom_bits(token: &[u8]) -> impl Iterator<Item = usize> {
+    let hash = token.iter().fold(0xcbf29ce484222325_u64, |h, b| {
+        (h ^ *b as u64).wrapping_mul(0x100000001b3)
+    });
+    let (h1, h2) = (hash & 0xffffffff, (hash >> 32) | 1);
+
+    (0..BLOOM_HASHES)
+        .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % (BLOOM_BYTES as u64 * 8)) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_dir::TestDir, timestamp};
+
+    #[test]
+    fn block_filters() {
+        let mut block = Block::new(0, 10);
+        block.add_line("2022-01-01 07:00:00,0 [info] module1 started");
+        block.add_line("2022-01-01 09:00:00,0 [debug] module2 stopped");
+
+        assert_eq!(block.lines(), 10..12);
+        assert!(block.overlaps_lines(0, 10));
+        assert!(!block.overlaps_lines(12, 20));
+        assert!(block.may_contain("[debug]"));
+        assert!(block.may_contain("ok"));
+        assert!(!block.may_contain("[warning]"));
+
+        let bound = |v| timestamp::parse_bound(v, false).unwrap();
+        assert!(block.overlaps_time(&bound("2022-01-01 08:00"), &bound("2022-01-01 08:30")));
+        assert!(!block.overlaps_time(&bound("2022-01-02"), &bound("2022-01-03")));
+    }
+
+    #[test]
+    fn index_trailing_whitespace() {
+        let dir = TestDir::new("index-whitespace");
+        let path = dir.join("app.log");
+        fs::write(&path, "status ok  \r\nkey\tvalue\t\n").unwrap();
+
+        // Keywords ending in whitespace can be found in the blocks of lines that contain them.
+        let index = Index::build(&path).unwrap();
+        assert!(index.blocks()[0].may_contain("ok "));
+        assert!(index.blocks()[0].may_contain("value\t"));
+    }
+
+    #[test]
+    fn write_and_load_index() {
+        let dir = TestDir::new("index");
+        let path = dir.join("app.log");
+        fs::write(&path, "first line\nsecond line\n").unwrap();
+
+        let index = Index::build(&path).unwrap();
+        index.write(&path).unwrap();
+
+        let loaded = Index::load(&path).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.blocks()[0].lines(), 0..2);
+        assert!(loaded.blocks()[0].may_contain("second"));
+
+        // Changing the file invalidates the index.
+        fs::write(&path, "first line\nsecond line\nthird line\n").unwrap();
+        assert!(Index::load(&path).is_none());
+
+        // Unreadable indexes are ignored.
+        let index = Index::build(&path).unwrap();
+        let sidecar = index.write(&path).unwrap();
+        let bytes = fs::read(&sidecar).unwrap();
+        fs::write(&sidecar, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(Index::load(&path).is_none());
+        fs::write(&sidecar, MAGIC).unwrap();
+        assert!(Index::load(&path).is_none());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{error::Error, Result};
+use glob::Pattern;
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Bytes checked for a NUL byte when deciding if a file is binary.
+const BINARY_CHECK_LEN: u64 = 8 * 1024;
+
+/// Orders to sort expanded inputs in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    /// Sort by path.
+    Name,
+    /// Sort by modification time, oldest first.
+    Modified,
+}
+
+impl SortKey {
+    /// Parse a sort key name.
+    pub fn parse(name: &str) -> Option<SortKey> {
+        match name {
+            "name" => Some(SortKey::Name),
+            "mtime" => Some(SortKey::Modified),
+            _ => None,
+        }
+    }
+}
+
+/// Options for expanding path arguments into the log files to query. Directories and glob
+/// patterns are expanded, while files named directly are always kept.
+#[derive(Clone, Default)]
+pub struct Inputs {
+    recursive: bool,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    binary: bool,
+    sort: Option<SortKey>,
+}
+
+impl Inputs {
+    /// Create default `Inputs`.
+    pub fn new() -> Inputs {
+        Inputs::default()
+    }
+
+    /// Set whether directories are traversed recursively and return new `Inputs`.
+    pub fn with_recursive(self, recursive: bool) -> Inputs {
+        let mut inputs = self;
+
+        inputs.recursive = recursive;
+
+        inputs
+    }
+
+    /// Add a pattern expanded files must match and return new `Inputs`.
+    pub fn add_include(self, pattern: Pattern) -> Inputs {
+        let mut inputs = self;
+
+        inputs.include.push(pattern);
+
+        inputs
+    }
+
+    /// Add a pattern for expanded files to skip and return new `Inputs`.
+    pub fn add_exclude(self, pattern: Pattern) -> Inputs {
+        let mut inputs = self;
+
+        inputs.exclude.push(pattern);
+
+        inputs
+    }
+
+    /// Set whether expanded binary files are kept and return new `Inputs`.
+    pub fn with_binary(self, binary: bool) -> Inputs {
+        let mut inputs = self;
+
+        inputs.binary = binary;
+
+        inputs
+    }
+
+    /// Set the order to sort every input in and return new `Inputs`.
+    pub fn with_sort(self, sort: SortKey) -> Inputs {
+        let mut inputs = self;
+
+        inputs.sort = Some(sort);
+
+        inputs
+    }
+
+    /// Expand path arguments into files. Without a sort order inputs keep the order of their
+    /// arguments, and the files expanded from each argument are sorted by name. A path is only
+    /// kept once.
+    pub fn expand(&self, args: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        let mut res = vec![];
+
+        for arg in args {
+            let mut files = vec![];
+            let expanded = if arg.is_dir() {
+                self.walk(arg, &mut files)?;
+                true
+            } else if !arg.exists() && is_pattern(arg) {
+                self.glob(arg, &mut files)?
+            } else {
+                false
+            };
+
+            // Files, and patterns matching nothing, are kept so they're reported if unreadable.
+            if !expanded {
+                res.push(arg.to_owned());
+                continue;
+            }
+
+            files.sort();
+            res.extend(files);
+        }
+
+        let mut seen = HashSet::new();
+        res.retain(|p| seen.insert(p.to_owned()));
+
+        match self.sort {
+            Some(SortKey::Name) => res.sort(),
+            Some(SortKey::Modified) => res.sort_by_cached_key(|p| modified(p)),
+            None => {}
+        }
+
+        Ok(res)
+    }
+
+    /// Collect the files of a directory. Subdirectories are only walked when recursive, and
+    /// symlinked directories are never walked to avoid cycles.
+    fn walk(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir).map_err(|e| Error::from(e).with_path(dir))?;
+
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::from(e).with_path(dir))?;
+            let path = entry.path();
+            let kind = entry
+                .file_type()
+                .map_err(|e| Error::from(e).with_path(&path))?;
+
+            if kind.is_dir() {
+                if self.recursive {
+                    self.walk(&path, files)?;
+                }
+            } else if path.is_file() && self.keeps(&path) {
+                files.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Collect the files matched by a glob pattern. Directories matched are expanded like
+    /// directory arguments. Return `false` if nothing matched the pattern.
+    fn glob(&self, pattern: &Path, files: &mut Vec<PathBuf>) -> Result<bool> {
+        let paths = glob::glob(&pattern.to_string_lossy())
+            .map_err(|e| Error::Parse(format!("{}: {}", pattern.display(), e)))?;
+        let mut matched = false;
+
+        for path in paths {
+            matched = true;
+            let path = path.map_err(|e| {
+                let path = e.path().to_owned();
+                Error::from(io::Error::from(e)).with_path(&path)
+            })?;
+
+            if path.is_dir() {
+                self.walk(&path, files)?;
+            } else if self.keeps(&path) {
+                files.push(path);
+            }
+        }
+
+        Ok(matched)
+    }
+
+    /// Check if an expanded file passes the include and exclude patterns and binary check.
+    /// Patterns match a file's name or its whole path.
+    fn keeps(&self, path: &Path) -> bool {
+        let matches = |pattern: &Pattern| {
+            pattern.matches_path(path)
+                || path
+                    .file_name()
+                    .is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        };
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+            && (self.binary || !is_binary(path))
+    }
+}
+
+/// Check if a path argument is a glob pattern.
+fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
+
+/// Check if a file looks binary. Files with a NUL byte near their start are binary unless they
+/// start with a UTF-16 byte order mark. Files that can't be read are left to fail when queried.
+fn is_binary(path: &Path) -> bool {
+    let mut start = vec![];
+
+    if File::open(path)
+        .and_then(|f| f.take(BINARY_CHECK_LEN).read_to_end(&mut start))
+        .is_err()
+    {
+        return false;
+    }
+
+    !start.starts_with(&[0xff, 0xfe]) && !start.starts_with(&[0xfe, 0xff]) && start.contains(&0)
+}
+
+/// Modification time of a file. Files without one sort first.
+fn modified(path: &Path) -> SystemTime {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn expand_directories_and_patterns() {
+        let test_dir = TestDir::new("inputs");
+        let dir = test_dir.path().to_path_buf();
+        fs::create_dir_all(dir.join("nested")).unwrap();
+
+        fs::write(dir.join("b.log"), "b\n").unwrap();
+        fs::write(dir.join("a.log"), "a\n").unwrap();
+        fs::write(dir.join("notes.txt"), "notes\n").unwrap();
+        fs::write(dir.join("data.log"), b"\x00\x01\x02").unwrap();
+        fs::write(dir.join("nested").join("c.log"), "c\n").unwrap();
+
+        let names = |paths: Vec<PathBuf>| -> Vec<String> {
+            paths
+                .iter()
+                .map(|p| p.strip_prefix(&dir).unwrap().display().to_string())
+                .collect()
+        };
+
+        let inputs = Inputs::new().add_include(Pattern::new("*.log").unwrap());
+        assert_eq!(
+            names(inputs.expand(std::slice::from_ref(&dir)).unwrap()),
+            vec!["a.log", "b.log"]
+        );
+
+        let inputs = inputs
+            .with_recursive(true)
+            .add_exclude(Pattern::new("a.*").unwrap());
+        assert_eq!(
+            names(inputs.expand(std::slice::from_ref(&dir)).unwrap()),
+            vec!["b.log", "nested/c.log"]
+        );
+
+        let pattern = dir.join("**").join("*.log");
+        let inputs = Inputs::new().with_binary(true);
+        assert_eq!(
+            names(inputs.expand(&[pattern, dir.join("a.log")]).unwrap()),
+            vec!["a.log", "b.log", "data.log", "nested/c.log"]
+        );
+    }
+}
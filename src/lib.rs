@@ -0,0 +1,96 @@
+//! Query engine of `log-cli`, for filtering log files from Rust.
+//!
+//! A [`Query`] is built from [`Instructions`] listing the [`LineSource`]s to read and how their
+//! lines are filtered. Its [`Matches`] yield each line kept as a [`Match`], one source after
+//! another, and [`Query::execute`] writes the lines kept to a [`Sink`].
+//!
+//! ```
+//! use log_cli::{Instructions, MemorySource, Query};
+//!
+//! let text = "2022-01-01 07:00:00 [info] started\n2022-01-01 07:00:01 [error] failed\n";
+//! let instructions = Instructions::new()
+//!     .add_source(MemorySource::new("app.log".into(), text.into()))
+//!     .add_keyword("[error]".to_string());
+//!
+//! let lines = Query::build(instructions)?
+//!     .matches()
+//!     .map(|m| m.map(|m| (m.ln(), m.line().to_string())))
+//!     .collect::<log_cli::Result<Vec<_>>>()?;
+//!
+//! assert_eq!(lines, vec![(1, "2022-01-01 07:00:01 [error] failed".to_string())]);
+//! # Ok::<(), log_cli::Error>(())
+//! ```
+//!
+//! The `log-cli` binary parses its arguments into `Instructions` using only this API. It's built
+//! with the default `cli` feature, which library users can turn off to leave out its
+//! dependencies. The `color` feature colours lines written by a [`TextSink`].
+
+#[cfg(feature = "color")]
+pub use color::Palette;
+pub use correlate::{Correlation, IdPattern};
+pub use encoding::Encoding;
+pub use error::Error;
+pub use fields::Level;
+pub use gaps::Gaps;
+pub use index::Index;
+pub use inputs::{Inputs, SortKey};
+pub use lines::{EnumeratedLines, Lines};
+pub use patterns::Patterns;
+pub use pipeline::{Pipeline, RepeatMode, Repeats, StageKind};
+pub use query::{Instructions, Match, Matches, Query, Selection};
+pub use sink::{JsonSink, Sink, TextSink};
+pub use sort::{LineOrder, SortBy};
+pub use source::{FileSource, GzipSource, LineSource, MemorySource, StdinSource, TarSource};
+pub use spikes::{SpikeRule, Spikes, Threshold};
+
+/// Buffer operations for IO.
+mod buffer;
+/// Colouring of displayed lines by level.
+#[cfg(feature = "color")]
+mod color;
+/// Correlation of lines across files by the IDs they share.
+mod correlate;
+/// Text encodings for decoding log files.
+mod encoding;
+/// Error types for `log-cli`.
+mod error;
+/// Parsing of levels and fields from log lines.
+mod fields;
+/// Detection of gaps and inversions in line timestamps.
+mod gaps;
+/// Sidecar indexes for fast repeat queries.
+mod index;
+/// Expansion of path arguments into log files.
+mod inputs;
+/// Data structures for file data.
+mod lines;
+/// Message template mining for clustering lines.
+mod patterns;
+/// Ordered stages applied to queried lines.
+mod pipeline;
+/// Query implementations for data operations.
+mod query;
+/// Redaction of sensitive values in lines.
+pub mod redact;
+/// Discovery of rotated log sets.
+pub mod rotated;
+/// Parallel scanning of large files.
+mod scan;
+/// Multi-pattern keyword search over raw bytes.
+mod search;
+/// Destinations query results are written to.
+mod sink;
+/// Sorting of lines, spilling to temporary files past a memory budget.
+mod sort;
+/// Sources lines are read from.
+mod source;
+/// Detection of spikes in the number of lines logged over time.
+mod spikes;
+/// Temporary directories for tests.
+#[cfg(test)]
+mod test_dir;
+/// Timestamp parsing for log lines.
+pub mod timestamp;
+
+/// Result type for all errors.
+pub type Result<T> = ::std::result::Result<T, Error>;
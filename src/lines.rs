@@ -1,51 +1,206 @@
-use crate::{buffer, Result};
-use std::{fmt, io::BufRead, path::PathBuf};
+use crate::{buffer, encoding::Encoding, index::Index, Result};
+use memchr::memchr_iter;
+use std::{
+    collections::HashSet,
+    io::{BufRead, Seek, SeekFrom},
+    path::PathBuf,
+};
 
 /// Alias to enumerated lines data structure.
-pub(crate) type EnumeratedLines = Vec<(usize, String)>;
+pub type EnumeratedLines = Vec<(usize, String)>;
 
-/// Collected and enumerated line strings.
-pub(crate) struct Lines(EnumeratedLines);
+/// Collected and enumerated line strings, with the line numbers of lines that had invalid bytes
+/// replaced when they were decoded. Lines read from several segments can be labelled with the
+/// segment each line came from.
+pub struct Lines {
+    lines: EnumeratedLines,
+    replaced: Vec<usize>,
+    segments: Vec<(usize, String)>,
+}
 
 #[allow(dead_code)]
 impl Lines {
     /// Create `Lines` from already enumerated lines.
     pub(crate) fn new(lines: EnumeratedLines) -> Lines {
-        Lines(lines)
+        Lines {
+            lines,
+            replaced: vec![],
+            segments: vec![],
+        }
+    }
+
+    /// Set the first line number and label of each segment and return new `Lines`.
+    pub(crate) fn with_segments(self, segments: Vec<(usize, String)>) -> Lines {
+        let mut lines = self;
+
+        lines.segments = segments;
+
+        lines
+    }
+
+    /// Append all lines read from a segment, numbering them after the lines collected. The
+    /// segment is labelled if a label is given.
+    pub(crate) fn append(&mut self, lines: Lines, label: Option<String>) {
+        let offset = self.lines.len();
+
+        if let Some(label) = label {
+            self.segments.push((offset, label));
+        }
+
+        self.replaced
+            .extend(lines.replaced.iter().map(|ln| offset + ln));
+        self.lines.extend(
+            lines
+                .lines
+                .into_iter()
+                .map(|(ln, line)| (offset + ln, line)),
+        );
+    }
+
+    /// Set the line numbers of lines with replaced bytes and return new `Lines`. Only line
+    /// numbers of lines collected are kept.
+    pub(crate) fn with_replaced(self, replaced: Vec<usize>) -> Lines {
+        let mut lines = self;
+
+        lines.replaced = replaced;
+        lines.retain_replaced();
+
+        lines
+    }
+
+    /// Create `Lines` from a filepath. Lines are split on raw bytes, so lines with invalid bytes
+    /// are kept and numbered like any other line.
+    pub(crate) fn read(path: &PathBuf, encoding: Encoding) -> Result<Lines> {
+        if !encoding.is_byte_oriented() {
+            return encoding.read_lines(path);
+        }
+
+        Lines::read_range(path, 0, usize::MAX, encoding)
+    }
+
+    /// Create `Lines` from the lines of a filepath numbered from `start` up to `end`. Reading
+    /// stops once `end` is reached.
+    pub(crate) fn read_range(
+        path: &PathBuf,
+        start: usize,
+        end: usize,
+        encoding: Encoding,
+    ) -> Result<Lines> {
+        let mut buf = buffer::read_file(path)?;
+        let mut line = vec![];
+        let mut res = Lines::new(vec![]);
+
+        // Skip lines before the range without keeping them.
+        for _ in 0..start {
+            line.clear();
+            if buf.read_until(b'\n', &mut line)? == 0 {
+                return Ok(res);
+            }
+        }
+
+        for ln in start..end {
+            if !buffer::read_raw_line(&mut buf, &mut line)? {
+                break;
+            }
+            res.push_decoded(ln, &line, encoding);
+        }
+
+        Ok(res)
+    }
+
+    /// Create `Lines` from the last `n` lines of a filepath. The file is read backwards from its
+    /// end, so only the lines selected are read.
+    pub(crate) fn read_tail(path: &PathBuf, n: usize, encoding: Encoding) -> Result<Lines> {
+        let mut buf = buffer::read_file(path)?;
+        let offset = buffer::tail_offset(buf.get_mut(), n)?;
+        let mut line = vec![];
+        let mut res = Lines::new(vec![]);
+
+        buf.seek(SeekFrom::Start(offset))?;
+        while buffer::read_raw_line(&mut buf, &mut line)? {
+            res.push_decoded(res.lines.len(), &line, encoding);
+        }
+
+        // Lines before the tail are counted from the indexed block it starts in. Without a fresh
+        // index, the line endings before the tail are counted in a map of the file.
+        let block = Index::load(path).and_then(|index| {
+            index
+                .block_at(offset)
+                .map(|block| (block.offset(), block.lines().start))
+        });
+        let first = match (block, buffer::map_file(path)?) {
+            (Some((start, ln)), _) => ln + buffer::count_lines_between(&mut buf, start, offset)?,
+            (None, Some(map)) => memchr_iter(b'\n', &map[..offset as usize]).count(),
+            (None, None) => buffer::count_lines_between(&mut buf, 0, offset)?,
+        };
+
+        for (ln, _) in res.lines.iter_mut() {
+            *ln += first;
+        }
+        for ln in res.replaced.iter_mut() {
+            *ln += first;
+        }
+
+        Ok(res)
+    }
+
+    /// Add line `ln`, noting whether it had invalid bytes replaced.
+    pub(crate) fn push(&mut self, ln: usize, line: String, replaced: bool) {
+        if replaced {
+            self.replaced.push(ln);
+        }
+        self.lines.push((ln, line));
     }
 
-    /// Create `Lines` from a filepath.
-    pub(crate) fn read(path: &PathBuf) -> Result<Lines> {
-        let buf = buffer::read_file(path)?;
+    /// Decode a line's bytes and add it as line `ln`.
+    pub(crate) fn push_decoded(&mut self, ln: usize, bytes: &[u8], encoding: Encoding) {
+        let (line, replaced) = encoding.decode(bytes);
 
-        Ok(Lines(buf.lines().flatten().enumerate().collect()))
+        self.push(ln, line, replaced);
     }
 
     /// Access enumerated lines collected.
-    pub(crate) fn enumerated_lines(&self) -> &EnumeratedLines {
-        &self.0
+    pub fn enumerated_lines(&self) -> &EnumeratedLines {
+        &self.lines
     }
 
-    /// Update wrapped enumerated lines.
-    pub(crate) fn set_lines(&mut self, lines: EnumeratedLines) {
-        self.0 = lines
+    /// Take the enumerated lines collected.
+    pub(crate) fn into_enumerated_lines(self) -> EnumeratedLines {
+        self.lines
+    }
+
+    /// Access the first line number and label of each labelled segment.
+    pub(crate) fn segments(&self) -> &[(usize, String)] {
+        &self.segments
     }
-}
 
-impl fmt::Display for Lines {
-    // This trait requires `fmt` with this exact signature.
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let lines = self.enumerated_lines();
+    /// Label of the segment line `ln` was read from, if segments are labelled.
+    pub(crate) fn segment_label(&self, ln: usize) -> Option<&str> {
+        let i = self.segments.partition_point(|(first, _)| *first <= ln);
 
-        // To pad line numbers, get the length of .len as a string.
-        let pad_len = lines.len().to_string().len();
+        i.checked_sub(1).map(|i| self.segments[i].1.as_str())
+    }
+
+    /// Access the line numbers of lines that had invalid bytes replaced.
+    pub(crate) fn replaced(&self) -> &[usize] {
+        &self.replaced
+    }
 
-        write!(f, "")?;
+    /// Update wrapped enumerated lines. Replaced line numbers are kept only for lines that
+    /// remain.
+    pub(crate) fn set_lines(&mut self, lines: EnumeratedLines) {
+        self.lines = lines;
+        self.retain_replaced();
+    }
 
-        for (ln, line) in lines.iter() {
-            write!(f, "\nln{:0width$} {}", ln, line, width = pad_len)?;
+    /// Drop replaced line numbers of lines that aren't collected.
+    fn retain_replaced(&mut self) {
+        if self.replaced.is_empty() {
+            return;
         }
 
-        Ok(())
+        let kept: HashSet<usize> = self.lines.iter().map(|(ln, _)| *ln).collect();
+
+        self.replaced.retain(|ln| kept.contains(ln));
     }
 }
@@ -1,6 +1,6 @@
-use args::Args;
+use log_cli::Error;
 use std::{
-    error,
+    io,
     process::{ExitCode, Termination},
 };
 
@@ -13,22 +13,27 @@ use std::{
 mod app;
 /// Argument implementations bridging clap inputs to `log-cli` components.
 mod args;
-/// Buffer operations for IO.
-mod buffer;
-/// Data structures for file data.
-mod lines;
-/// Query implementations for data operations.
-mod query;
-
-// Generic result type for all errors.
-type Result<T> = ::std::result::Result<T, Box<dyn error::Error>>;
+/// Commands of the `log-cli` binary.
+mod cli;
+/// Config files setting argument defaults and saved queries.
+mod config;
+/// Temporary directories for tests. Shared with the library, whose tests use all of it.
+#[cfg(test)]
+#[allow(dead_code)]
+mod test_dir;
+/// Interactive terminal pager.
+mod view;
 
 #[repr(u8)]
 enum MainResult {
     /// Process exit without issue.
     Success = 0,
+    /// Process exit after a query matched no lines.
+    NoMatch = 1,
     /// Process exit due to invalid behavior.
     Invalid = 2,
+    /// Process exit after some paths of a query failed and the rest were read.
+    Partial = 3,
 }
 
 impl Termination for MainResult {
@@ -38,27 +43,21 @@ impl Termination for MainResult {
 }
 
 fn main() -> MainResult {
-    // Exit with invalid behavior code if args fail to parse.
-    if let Err(e) = Args::parse().and_then(run) {
-        eprintln!("{}", e);
-        return MainResult::Invalid;
+    // Exit codes follow grep so scripts can branch on whether lines matched.
+    match cli::run() {
+        Ok(()) => MainResult::Success,
+        Err(Error::NoMatch) => MainResult::NoMatch,
+        // Readers that stop early (ex: head) close the pipe, which isn't an error of the query.
+        Err(Error::Io { source, .. }) if source.kind() == io::ErrorKind::BrokenPipe => {
+            MainResult::Success
+        }
+        Err(e @ Error::Partial { failed, total }) if failed < total => {
+            eprintln!("{}", e);
+            MainResult::Partial
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            MainResult::Invalid
+        }
     }
-
-    MainResult::Success
-}
-
-/// Determine root behavior selected.
-fn run(args: Args) -> Result<()> {
-    use args::Root::*;
-
-    match args.root()? {
-        Query => run_query(args),
-    }
-}
-
-/// Execute the main query behavior.
-fn run_query(args: Args) -> Result<()> {
-    use query::Query;
-
-    Query::build(args.to_instructions()?)?.execute()
 }
@@ -0,0 +1,346 @@
+use crate::lines::EnumeratedLines;
+use std::{collections::HashMap, fmt};
+
+/// Token used in place of template positions that vary between merged lines.
+const WILDCARD: &str = "<*>";
+
+/// A message template mined from one or more lines.
+pub(crate) struct Template {
+    tokens: Vec<String>,
+    count: usize,
+    first: usize,
+    last: usize,
+    example: (usize, String),
+}
+
+impl Template {
+    /// Create a `Template` from the masked tokens of its first line.
+    fn new(tokens: Vec<String>, ln: usize, line: &str) -> Template {
+        Template {
+            tokens,
+            count: 1,
+            first: ln,
+            last: ln,
+            example: (ln, line.to_string()),
+        }
+    }
+
+    /// Ratio of positions shared with `tokens`. Wildcards match any token.
+    fn similarity(&self, tokens: &[String]) -> f64 {
+        if self.tokens.is_empty() {
+            return 1.0;
+        }
+
+        let shared = self
+            .tokens
+            .iter()
+            .zip(tokens)
+            .filter(|(a, b)| a.as_str() == WILDCARD || a == b)
+            .count();
+
+        shared as f64 / self.tokens.len() as f64
+    }
+
+    /// Merge a line's tokens into the template, replacing positions that differ with wildcards.
+    fn merge(&mut self, tokens: Vec<String>, ln: usize) {
+        for (a, b) in self.tokens.iter_mut().zip(tokens) {
+            if *a != b {
+                *a = WILDCARD.to_string();
+            }
+        }
+
+        self.count += 1;
+        self.first = self.first.min(ln);
+        self.last = self.last.max(ln);
+    }
+
+    /// Access the template's tokens joined by spaces.
+    pub(crate) fn text(&self) -> String {
+        self.tokens.join(" ")
+    }
+}
+
+/// Templates mined from lines using a Drain-style clustering. Lines are grouped by token count and
+/// leading token, then merged into the most similar template in their group if the similarity
+/// threshold is met.
+pub struct Patterns {
+    similarity: f64,
+    templates: Vec<Template>,
+    groups: HashMap<(usize, String), Vec<usize>>,
+}
+
+impl Patterns {
+    /// Create empty `Patterns` using a similarity threshold between 0 and 1.
+    pub(crate) fn new(similarity: f64) -> Patterns {
+        Patterns {
+            similarity,
+            templates: vec![],
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Mine `Patterns` from enumerated lines.
+    pub fn mine(lines: &EnumeratedLines, similarity: f64) -> Patterns {
+        let mut patterns = Patterns::new(similarity);
+
+        for (ln, line) in lines.iter() {
+            patterns.add(*ln, line);
+        }
+
+        patterns
+    }
+
+    /// Add a line to the template it is most similar to, or create a new template.
+    pub(crate) fn add(&mut self, ln: usize, line: &str) {
+        let tokens = mask_line(line);
+        let leading = match tokens.first() {
+            Some(t) if is_masked(t) => WILDCARD.to_string(),
+            Some(t) => t.to_owned(),
+            None => String::new(),
+        };
+        let group = self.groups.entry((tokens.len(), leading)).or_default();
+
+        let best = group
+            .iter()
+            .map(|i| (*i, self.templates[*i].similarity(&tokens)))
+            .filter(|(_, sim)| *sim >= self.similarity)
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((i, _)) => self.templates[i].merge(tokens, ln),
+            None => {
+                group.push(self.templates.len());
+                self.templates.push(Template::new(tokens, ln, line));
+            }
+        }
+    }
+
+    /// Access templates ordered by count, most frequent first.
+    pub(crate) fn templates(&self) -> Vec<&Template> {
+        let mut res: Vec<&Template> = self.templates.iter().collect();
+        res.sort_by(|a, b| b.count.cmp(&a.count).then(a.first.cmp(&b.first)));
+
+        res
+    }
+}
+
+impl fmt::Display for Patterns {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let templates = self.templates();
+
+        // Pad counts and line numbers to the widest found.
+        let count_len = templates
+            .iter()
+            .map(|t| t.count.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad_len = templates
+            .iter()
+            .map(|t| t.last.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        write!(f, "")?;
+
+        for t in templates {
+            write!(
+                f,
+                "\n{:>count_len$} ln{:0pad_len$}-ln{:0pad_len$} {}",
+                t.count,
+                t.first,
+                t.last,
+                t.text(),
+                count_len = count_len,
+                pad_len = pad_len
+            )?;
+            write!(
+                f,
+                "\n{:count_len$} e.g. ln{:0pad_len$} {}",
+                "",
+                t.example.0,
+                t.example.1,
+                count_len = count_len,
+                pad_len = pad_len
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Check if a token is a mask created by `mask_token`.
+fn is_masked(token: &str) -> bool {
+    token.starts_with('<') && token.ends_with('>')
+}
+
+/// Split a line into whitespace separated tokens with variable tokens masked.
+fn mask_line(line: &str) -> Vec<String> {
+    mask_quoted(line)
+        .split_whitespace()
+        .map(mask_token)
+        .collect()
+}
+
+/// Replace quoted strings in a line with a mask. Single quotes are only treated as quotes at the
+/// start of a token so apostrophes are left alone.
+fn mask_quoted(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut chars = line.char_indices().peekable();
+    let mut prev: Option<char> = None;
+
+    while let Some((i, c)) = chars.next() {
+        let opens = c == '"'
+            || (c == '\'' && prev.is_none_or(|p| p.is_whitespace() || "=:([{".contains(p)));
+
+        if opens {
+            if let Some(end) = line[i + 1..].find(c) {
+                res.push_str("<STR>");
+
+                // Skip to the closing quote.
+                while let Some((j, _)) = chars.peek() {
+                    if *j > i + end + 1 {
+                        break;
+                    }
+                    chars.next();
+                }
+
+                prev = Some(c);
+                continue;
+            }
+        }
+
+        res.push(c);
+        prev = Some(c);
+    }
+
+    res
+}
+
+/// Mask a token if it looks variable. Surrounding punctuation and `key=` prefixes are kept.
+fn mask_token(token: &str) -> String {
+    if let Some((key, value)) = token.split_once('=') {
+        return format!("{}={}", key, mask_token(value));
+    }
+
+    let start = token
+        .find(|c: char| !"([{".contains(c))
+        .unwrap_or(token.len());
+    let end = token
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !")]},;:.!?".contains(*c))
+        .map_or(start, |(i, c)| i + c.len_utf8())
+        .max(start);
+    let core = &token[start..end];
+
+    let mask = if core.is_empty() || is_masked(core) {
+        None
+    } else if is_ip(core) {
+        Some("<IP>")
+    } else if is_hex(core) {
+        Some("<HEX>")
+    } else if is_number(core) {
+        Some("<NUM>")
+    } else if is_id(core) {
+        Some("<ID>")
+    } else {
+        None
+    };
+
+    match mask {
+        Some(m) => format!("{}{}{}", &token[..start], m, &token[end..]),
+        None => token.to_string(),
+    }
+}
+
+/// Check if a token is an IPv4 address, optionally with a port.
+fn is_ip(token: &str) -> bool {
+    let addr = match token.rsplit_once(':') {
+        Some((addr, port)) if !port.is_empty() && port.chars().all(|c| c.is_ascii_digit()) => addr,
+        _ => token,
+    };
+    let parts: Vec<&str> = addr.split('.').collect();
+
+    parts.len() == 4 && parts.iter().all(|p| p.parse::<u8>().is_ok())
+}
+
+/// Check if a token is hex-like, either `0x` prefixed or a long run of mixed hex digits.
+fn is_hex(token: &str) -> bool {
+    if let Some(digits) = token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        return !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit());
+    }
+
+    token.len() >= 8
+        && token.chars().all(|c| c.is_ascii_hexdigit())
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Check if a token is number-like. This includes dates, times and decimals.
+fn is_number(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit())
+        && token
+            .chars()
+            .all(|c| c.is_ascii_digit() || ".,:-+/_".contains(c))
+}
+
+/// Check if a token is an identifier mixing letters and digits (ex: uuids, module1).
+fn is_id(token: &str) -> bool {
+    token.chars().any(|c| c.is_ascii_digit())
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_variable_tokens() {
+        let line = "2022-01-01 07:00:00,0 [info] module1 from 10.0.0.1:8080 id=0xdeadbeef \"a b\"";
+
+        assert_eq!(
+            mask_line(line).join(" "),
+            "<NUM> <NUM> [info] <ID> from <IP> id=<HEX> <STR>"
+        );
+        assert_eq!(mask_line("it's done.").join(" "), "it's done.");
+    }
+
+    #[test]
+    fn mine_templates() {
+        let lines: EnumeratedLines = vec![
+            "user 12 logged in".to_string(),
+            "disk full".to_string(),
+            "user 40 logged in".to_string(),
+            "user alice logged in".to_string(),
+        ]
+        .into_iter()
+        .enumerate()
+        .collect();
+        let patterns = Patterns::mine(&lines, 0.5);
+        let templates = patterns.templates();
+
+        assert_eq!(templates.len(), 2);
+        assert_eq!(templates[0].text(), "user <*> logged in");
+        assert_eq!(templates[0].count, 3);
+        assert_eq!((templates[0].first, templates[0].last), (0, 3));
+        assert_eq!(templates[1].text(), "disk full");
+    }
+
+    #[test]
+    fn mine_templates_without_merging() {
+        let lines: EnumeratedLines = vec![
+            "user 12 logged in".to_string(),
+            "user alice logged in".to_string(),
+        ]
+        .into_iter()
+        .enumerate()
+        .collect();
+
+        assert_eq!(Patterns::mine(&lines, 1.0).templates().len(), 2);
+    }
+}
@@ -0,0 +1,658 @@
+use crate::{lines::EnumeratedLines, query::Selection, sort::LineOrder, timestamp::Parser};
+use chrono::NaiveDateTime;
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashSet},
+    fmt,
+};
+
+/// Kinds of stages a query pipeline is built from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageKind {
+    /// Select lines by position.
+    Select,
+    /// Keep lines matching keywords and a date range.
+    Filter,
+    /// Drop or collapse repeated lines.
+    Dedupe,
+    /// Sort lines by a key.
+    Sort,
+    /// Keep a number of lines.
+    Limit,
+    /// Add the lines surrounding each line kept.
+    Context,
+}
+
+impl StageKind {
+    /// Order stages are applied in when no order is given.
+    pub(crate) const DEFAULT_ORDER: [StageKind; 6] = [
+        StageKind::Select,
+        StageKind::Filter,
+        StageKind::Dedupe,
+        StageKind::Sort,
+        StageKind::Limit,
+        StageKind::Context,
+    ];
+
+    /// Parse a stage name.
+    pub fn parse(name: &str) -> Option<StageKind> {
+        StageKind::DEFAULT_ORDER
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+
+    /// Name of the stage used in pipeline specs and plans.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StageKind::Select => "select",
+            StageKind::Filter => "filter",
+            StageKind::Dedupe => "dedupe",
+            StageKind::Sort => "sort",
+            StageKind::Limit => "limit",
+            StageKind::Context => "context",
+        }
+    }
+}
+
+/// Keywords and an inclusive date range lines must match to be kept.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Filter {
+    keywords: Vec<String>,
+    any: bool,
+    date_range: Option<(NaiveDateTime, NaiveDateTime)>,
+    timestamps: Parser,
+}
+
+impl Filter {
+    /// Create a `Filter`. Lines must contain every keyword unless `any` is true.
+    pub(crate) fn new(
+        keywords: Vec<String>,
+        any: bool,
+        date_range: Option<(NaiveDateTime, NaiveDateTime)>,
+    ) -> Filter {
+        Filter {
+            keywords,
+            any,
+            date_range,
+            timestamps: Parser::default(),
+        }
+    }
+
+    /// Set how timestamps are read and return a new `Filter`. The date range is read in the zone
+    /// of the parser.
+    pub(crate) fn with_timestamps(self, timestamps: Parser) -> Filter {
+        let mut filter = self;
+
+        filter.timestamps = timestamps;
+
+        filter
+    }
+
+    /// Check if the filter keeps every line.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.keywords.is_empty() && self.date_range.is_none()
+    }
+
+    /// Check if a line contains the keywords of the filter.
+    pub(crate) fn matches_keywords(&self, line: &str) -> bool {
+        match self.any {
+            true => self.keywords.is_empty() || self.keywords.iter().any(|k| line.contains(k)),
+            false => self.keywords.iter().all(|k| line.contains(k)),
+        }
+    }
+
+    /// Check if a line has a timestamp within the date range, if one was set. Timestamps are
+    /// compared in UTC.
+    pub(crate) fn in_date_range(&self, line: &str) -> bool {
+        let zone = self.timestamps.zone();
+
+        self.date_range.as_ref().is_none_or(|(lower, upper)| {
+            self.timestamps
+                .instant(line)
+                .is_some_and(|t| t >= zone.to_utc(*lower) && t <= zone.to_utc(*upper))
+        })
+    }
+
+    /// Check if a line is kept by the filter.
+    pub(crate) fn keeps(&self, line: &str) -> bool {
+        self.in_date_range(line) && self.matches_keywords(line)
+    }
+}
+
+/// Ways to reduce repeated lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatMode {
+    /// Drop lines repeating any earlier line.
+    Drop,
+    /// Fold consecutive repeats into their first line, annotated with the repeats folded.
+    Collapse,
+}
+
+/// Reduces lines repeating the message of another line. Messages can ignore a leading timestamp
+/// and the value of numbers.
+#[derive(Clone, Debug)]
+pub struct Repeats {
+    mode: RepeatMode,
+    ignore_timestamp: bool,
+    normalize_numbers: bool,
+    timestamps: Parser,
+}
+
+impl Repeats {
+    /// Create `Repeats` comparing whole lines.
+    pub fn new(mode: RepeatMode) -> Repeats {
+        Repeats {
+            mode,
+            ignore_timestamp: false,
+            normalize_numbers: false,
+            timestamps: Parser::default(),
+        }
+    }
+
+    /// Set how timestamps are read and return new `Repeats`.
+    pub(crate) fn with_timestamps(self, timestamps: Parser) -> Repeats {
+        let mut repeats = self;
+
+        repeats.timestamps = timestamps;
+
+        repeats
+    }
+
+    /// Set whether timestamps are ignored and return new `Repeats`.
+    pub fn with_ignore_timestamp(self, ignore: bool) -> Repeats {
+        let mut repeats = self;
+
+        repeats.ignore_timestamp = ignore;
+
+        repeats
+    }
+
+    /// Set whether numbers with different values are treated as equal and return new `Repeats`.
+    pub fn with_normalize_numbers(self, normalize: bool) -> Repeats {
+        let mut repeats = self;
+
+        repeats.normalize_numbers = normalize;
+
+        repeats
+    }
+
+    /// Message of a line compared to find repeats.
+    fn message<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let line = match self.ignore_timestamp {
+            true => self.timestamps.strip(line),
+            false => Cow::Borrowed(line),
+        };
+
+        match self.normalize_numbers {
+            true => Cow::Owned(normalize_numbers(&line)),
+            false => line,
+        }
+    }
+
+    /// Reduce repeated lines. Lines kept keep their line number.
+    fn apply(&self, lines: EnumeratedLines) -> EnumeratedLines {
+        match self.mode {
+            RepeatMode::Drop => {
+                let mut seen = HashSet::new();
+
+                lines
+                    .into_iter()
+                    .filter(|(_, l)| seen.insert(self.message(l).into_owned()))
+                    .collect()
+            }
+            RepeatMode::Collapse => {
+                // Runs of repeats as their first line, last line number and count.
+                let mut runs: Vec<((usize, String), usize, usize)> = vec![];
+
+                for (ln, line) in lines {
+                    match runs.last_mut() {
+                        Some(((_, first), last, count))
+                            if self.message(first) == self.message(&line) =>
+                        {
+                            *last = ln;
+                            *count += 1;
+                        }
+                        _ => runs.push(((ln, line), ln, 1)),
+                    }
+                }
+
+                runs.into_iter()
+                    .map(|((ln, line), last, count)| match count {
+                        1 => (ln, line),
+                        _ => (
+                            ln,
+                            format!("{} (repeated {} times, ln{}-ln{})", line, count, ln, last),
+                        ),
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Repeats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mode {
+            RepeatMode::Drop => write!(f, "drop repeated lines")?,
+            RepeatMode::Collapse => write!(f, "collapse consecutive repeated lines")?,
+        }
+
+        match (self.ignore_timestamp, self.normalize_numbers) {
+            (true, true) => write!(f, " ignoring timestamps and numbers"),
+            (true, false) => write!(f, " ignoring timestamps"),
+            (false, true) => write!(f, " ignoring numbers"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+/// Replace each run of digits in a line with `#`.
+fn normalize_numbers(line: &str) -> String {
+    let mut res = String::with_capacity(line.len());
+    let mut digits = false;
+
+    for c in line.chars() {
+        match c.is_ascii_digit() {
+            true if digits => {}
+            true => res.push('#'),
+            false => res.push(c),
+        }
+        digits = c.is_ascii_digit();
+    }
+
+    res
+}
+
+/// Ways to limit the number of lines kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Limit {
+    /// Keep the last N lines.
+    Latest(usize),
+    /// Skip a number of lines and keep up to a number of the lines after them.
+    Page { skip: usize, take: Option<usize> },
+}
+
+/// A step of a query pipeline.
+#[derive(Clone, Debug)]
+pub(crate) enum Stage {
+    /// Keep lines selected by their position among the lines kept so far. Selecting first
+    /// selects lines by line number.
+    Select(Selection),
+    /// Keep lines matching the filter.
+    Filter(Filter),
+    /// Drop or collapse repeated lines.
+    Dedupe(Repeats),
+    /// Sort lines, keeping their line numbers.
+    Sort(LineOrder),
+    /// Keep a number of lines.
+    Limit(Limit),
+    /// Add the N lines before and after each line kept from the lines read.
+    Context(usize),
+}
+
+impl Stage {
+    /// Kind of the stage.
+    pub(crate) fn kind(&self) -> StageKind {
+        match self {
+            Stage::Select(_) => StageKind::Select,
+            Stage::Filter(_) => StageKind::Filter,
+            Stage::Dedupe(_) => StageKind::Dedupe,
+            Stage::Sort(_) => StageKind::Sort,
+            Stage::Limit(_) => StageKind::Limit,
+            Stage::Context(_) => StageKind::Context,
+        }
+    }
+
+    /// Apply the stage to the lines kept so far. Context is taken from `all`, the lines read.
+    fn apply(&self, lines: EnumeratedLines, all: &EnumeratedLines) -> EnumeratedLines {
+        match self {
+            Stage::Select(selection) => {
+                let len = lines.len();
+
+                lines
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| selection.contains(*i, len))
+                    .map(|(_, line)| line)
+                    .collect()
+            }
+            Stage::Filter(filter) => lines.into_iter().filter(|(_, l)| filter.keeps(l)).collect(),
+            Stage::Dedupe(repeats) => repeats.apply(lines),
+            Stage::Sort(order) => order.sort(lines),
+            Stage::Limit(Limit::Latest(n)) => {
+                let skip = lines.len().saturating_sub(*n);
+
+                lines.into_iter().skip(skip).collect()
+            }
+            Stage::Limit(Limit::Page { skip, take }) => lines
+                .into_iter()
+                .skip(*skip)
+                .take(take.unwrap_or(usize::MAX))
+                .collect(),
+            Stage::Context(n) => {
+                let around: BTreeSet<usize> = lines
+                    .iter()
+                    .flat_map(|(ln, _)| ln.saturating_sub(*n)..=ln.saturating_add(*n))
+                    .collect();
+
+                all.iter()
+                    .filter(|(ln, _)| around.contains(ln))
+                    .cloned()
+                    .collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<8}", self.kind().name())?;
+
+        match self {
+            Stage::Select(selection) => write!(f, "lines {}", selection),
+            Stage::Filter(filter) => {
+                let mut conditions = vec![];
+
+                if let Some((lower, upper)) = &filter.date_range {
+                    conditions.push(format!(
+                        "logged {} to {} {}",
+                        lower,
+                        upper,
+                        filter.timestamps.zone()
+                    ));
+                }
+
+                if !filter.keywords.is_empty() {
+                    let strategy = if filter.any { "any" } else { "all" };
+                    conditions.push(format!(
+                        "{} of keywords {:?}",
+                        strategy,
+                        filter.keywords.join(" ")
+                    ));
+                }
+
+                write!(f, "{}", conditions.join(" and "))
+            }
+            Stage::Dedupe(repeats) => write!(f, "{}", repeats),
+            Stage::Sort(order) => write!(f, "lines {}", order),
+            Stage::Limit(Limit::Latest(n)) => write!(f, "latest {}", describe_lines(*n)),
+            Stage::Limit(Limit::Page { skip, take }) => match (skip, take) {
+                (0, Some(take)) => write!(f, "first {}", describe_lines(*take)),
+                (skip, None) => write!(f, "skip {}", describe_lines(*skip)),
+                (skip, Some(take)) => write!(
+                    f,
+                    "skip {}, then first {}",
+                    describe_lines(*skip),
+                    describe_lines(*take)
+                ),
+            },
+            Stage::Context(n) => write!(f, "{} around each line", describe_lines(*n)),
+        }
+    }
+}
+
+/// Describe a number of lines.
+fn describe_lines(n: usize) -> String {
+    match n {
+        1 => "1 line".to_string(),
+        n => format!("{} lines", n),
+    }
+}
+
+/// Ordered stages applied to the lines read from a path.
+#[derive(Clone, Debug, Default)]
+pub struct Pipeline(Vec<Stage>);
+
+impl Pipeline {
+    /// Create a `Pipeline` applying stages in order.
+    pub(crate) fn new(stages: Vec<Stage>) -> Pipeline {
+        Pipeline(stages)
+    }
+
+    /// Selection of the first stage, if it selects lines.
+    pub(crate) fn leading_selection(&self) -> Option<&Selection> {
+        match self.0.first() {
+            Some(Stage::Select(selection)) => Some(selection),
+            _ => None,
+        }
+    }
+
+    /// Check if the pipeline can be applied while reading lines. Lines must be selected before
+    /// they're filtered, and filtered before any other stage, and no stage can need the lines
+    /// around those kept.
+    pub(crate) fn streams(&self) -> bool {
+        let kinds: Vec<StageKind> = self.0.iter().map(Stage::kind).collect();
+        let leading = usize::from(self.leading_selection().is_some());
+
+        !kinds.contains(&StageKind::Context)
+            && kinds[leading..].iter().all(|k| *k != StageKind::Select)
+            && kinds[leading..]
+                .iter()
+                .position(|k| *k == StageKind::Filter)
+                .is_none_or(|i| i == 0)
+    }
+
+    /// Number of lines read that can be kept at most, if reading can stop once they're found.
+    /// Lines are counted after the leading selection and filter, and reading can only stop early
+    /// if they're followed by a limit keeping the first lines.
+    pub(crate) fn read_limit(&self) -> Option<usize> {
+        if !self.streams() {
+            return None;
+        }
+
+        let leading = self
+            .0
+            .iter()
+            .take_while(|s| matches!(s.kind(), StageKind::Select | StageKind::Filter))
+            .count();
+
+        self.page_end(leading)
+    }
+
+    /// Number of lines kept by the stage at position `i`, if it keeps the first lines of a page.
+    fn page_end(&self, i: usize) -> Option<usize> {
+        match self.0.get(i) {
+            Some(Stage::Limit(Limit::Page {
+                skip,
+                take: Some(take),
+            })) => Some(skip.saturating_add(*take)),
+            _ => None,
+        }
+    }
+
+    /// Position and order of a sort that lines can be pushed to while they're read, so only the
+    /// lines the sort holds in memory are kept at once. Only filters can come before the sort, and
+    /// no stage can need the lines around those kept.
+    pub(crate) fn streamed_sort(&self) -> Option<(usize, &LineOrder)> {
+        let i = self.0.iter().position(|s| s.kind() == StageKind::Sort)?;
+
+        if self.0[..i].iter().any(|s| s.kind() != StageKind::Filter)
+            || self.0.iter().any(|s| s.kind() == StageKind::Context)
+        {
+            return None;
+        }
+
+        match &self.0[i] {
+            Stage::Sort(order) => Some((i, order)),
+            _ => None,
+        }
+    }
+
+    /// Number of sorted lines needed by the stages following the stage at position `i`, if the
+    /// stages only keep the first lines.
+    pub(crate) fn sorted_limit(&self, i: usize) -> Option<usize> {
+        self.page_end(i + 1)
+    }
+
+    /// Apply the stages following the stage at position `i` to the lines it kept.
+    pub(crate) fn run_after(&self, i: usize, lines: EnumeratedLines) -> EnumeratedLines {
+        self.run_stages(&self.0[i + 1..], lines)
+    }
+
+    /// Apply every stage to the lines read.
+    pub(crate) fn run(&self, lines: EnumeratedLines) -> EnumeratedLines {
+        self.run_stages(&self.0, lines)
+    }
+
+    /// Apply the stages following a leading selection to lines already selected.
+    pub(crate) fn run_selected(&self, lines: EnumeratedLines) -> EnumeratedLines {
+        let leading = usize::from(self.leading_selection().is_some());
+
+        self.run_stages(&self.0[leading..], lines)
+    }
+
+    // Apply stages in order.
+    fn run_stages(&self, stages: &[Stage], lines: EnumeratedLines) -> EnumeratedLines {
+        let all = match stages.iter().any(|s| s.kind() == StageKind::Context) {
+            true => lines.clone(),
+            false => vec![],
+        };
+
+        stages
+            .iter()
+            .fold(lines, |res, stage| stage.apply(res, &all))
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Pipeline:")?;
+
+        if self.0.is_empty() {
+            writeln!(f, "  every line")?;
+        }
+
+        for (i, stage) in self.0.iter().enumerate() {
+            writeln!(f, "  {}. {}", i + 1, stage)?;
+        }
+
+        if self.streamed_sort().is_some() {
+            return write!(f, "Lines are filtered and sorted while files are read.");
+        }
+
+        match self.streams() {
+            true => write!(f, "Lines are selected and filtered while files are read."),
+            false => write!(f, "Files are read in full before the first stage."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sort::SortBy;
+
+    fn lines(n: usize) -> EnumeratedLines {
+        (0..n)
+            .map(|i| {
+                (
+                    i,
+                    format!("line {}", if i % 3 == 0 { "error" } else { "info" }),
+                )
+            })
+            .collect()
+    }
+
+    fn numbers(lines: EnumeratedLines) -> Vec<usize> {
+        lines.iter().map(|(ln, _)| *ln).collect()
+    }
+
+    #[test]
+    fn apply_stages_in_order() {
+        let head = Stage::Select(Selection::parse(":5").unwrap());
+        let errors = Stage::Filter(Filter::new(vec!["error".to_string()], false, None));
+
+        // Error lines among the first 5 lines.
+        let pipeline = Pipeline::new(vec![head.clone(), errors.clone()]);
+        assert_eq!(numbers(pipeline.run(lines(20))), vec![0, 3]);
+        assert!(pipeline.streams());
+
+        // First 5 error lines.
+        let pipeline = Pipeline::new(vec![errors.clone(), head]);
+        assert_eq!(numbers(pipeline.run(lines(20))), vec![0, 3, 6, 9, 12]);
+        assert!(!pipeline.streams());
+
+        let pipeline = Pipeline::new(vec![
+            errors,
+            Stage::Limit(Limit::Latest(2)),
+            Stage::Context(1),
+        ]);
+        assert_eq!(
+            numbers(pipeline.run(lines(20))),
+            vec![14, 15, 16, 17, 18, 19]
+        );
+        assert_eq!(pipeline.read_limit(), None);
+    }
+
+    #[test]
+    fn reduce_repeated_lines() {
+        let lines: EnumeratedLines = [
+            "2022-01-01 07:00:00,0 retry 1 failed",
+            "2022-01-01 07:00:01,0 retry 2 failed",
+            "2022-01-01 07:00:01,0 retry 2 failed",
+            "2022-01-01 07:00:02,0 connected",
+            "2022-01-01 07:00:03,0 retry 1 failed",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (i + 10, l.to_string()))
+        .collect();
+
+        let drop = Repeats::new(RepeatMode::Drop);
+        assert_eq!(numbers(drop.apply(lines.clone())), vec![10, 11, 13, 14]);
+
+        let drop = drop.with_ignore_timestamp(true);
+        assert_eq!(numbers(drop.apply(lines.clone())), vec![10, 11, 13]);
+
+        let collapse = Repeats::new(RepeatMode::Collapse)
+            .with_ignore_timestamp(true)
+            .with_normalize_numbers(true);
+        let res = collapse.apply(lines);
+        assert_eq!(numbers(res.clone()), vec![10, 13, 14]);
+        assert_eq!(
+            res[0].1,
+            "2022-01-01 07:00:00,0 retry 1 failed (repeated 3 times, ln10-ln12)"
+        );
+    }
+
+    #[test]
+    fn limit_pages_of_lines() {
+        let errors = Stage::Filter(Filter::new(vec!["error".to_string()], false, None));
+        let page = Stage::Limit(Limit::Page {
+            skip: 2,
+            take: Some(3),
+        });
+
+        let pipeline = Pipeline::new(vec![errors.clone(), page.clone()]);
+        assert_eq!(numbers(pipeline.run(lines(20))), vec![6, 9, 12]);
+        assert_eq!(pipeline.read_limit(), Some(5));
+
+        // A page taken before filtering can't stop reading once enough lines are filtered.
+        let pipeline = Pipeline::new(vec![page, errors]);
+        assert_eq!(numbers(pipeline.run(lines(20))), vec![3]);
+        assert_eq!(pipeline.read_limit(), None);
+    }
+
+    #[test]
+    fn sort_while_reading() {
+        let errors = Stage::Filter(Filter::new(vec!["error".to_string()], false, None));
+        let sort = Stage::Sort(LineOrder::new(SortBy::Level, false));
+        let page = Stage::Limit(Limit::Page {
+            skip: 1,
+            take: Some(2),
+        });
+
+        let pipeline = Pipeline::new(vec![errors.clone(), sort.clone(), page]);
+        assert_eq!(pipeline.streamed_sort().map(|(i, _)| i), Some(1));
+        assert_eq!(pipeline.sorted_limit(1), Some(3));
+
+        // Lines can't be sorted while they're read once other stages come first.
+        let head = Stage::Select(Selection::parse(":5").unwrap());
+        let pipeline = Pipeline::new(vec![head, errors, sort.clone()]);
+        assert!(pipeline.streamed_sort().is_none());
+
+        // Context needs every line read.
+        let pipeline = Pipeline::new(vec![sort, Stage::Context(1)]);
+        assert!(pipeline.streamed_sort().is_none());
+    }
+}
@@ -1,21 +1,211 @@
+use crate::error::Error;
 use crate::{
+    buffer,
+    encoding::Encoding,
+    index::{Block, Index},
     lines::{EnumeratedLines, Lines},
+    pipeline::{Filter, Limit, Pipeline, Repeats, Stage, StageKind},
+    redact::Redactor,
+    rotated, scan,
+    search::Matcher,
+    sink::{self, Sink},
+    sort::LineOrder,
+    source::{FileSource, LineSource},
+    timestamp::{Parser, Zone},
     Result,
 };
-use std::path::PathBuf;
-
-/// Kinds of ranges that can be executed.
-enum RangeKind {
-    /// Standard line range to select data between a lower bound line number and an upper bound.
-    LineRange((usize, usize)),
-    /// Relative range from head of file.
-    Head(usize),
-    /// Relateive range from tail of file.
-    Tail(usize),
+use chrono::NaiveDateTime;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread, vec,
+};
+
+/// Replaced line numbers listed when reporting lines with invalid bytes.
+const REPORTED_LINES: usize = 10;
+
+/// Python-style slice of line numbers selecting lines from `start` up to, but excluding, `stop`
+/// every `step` lines. Negative indices count back from the end of the file and missing indices
+/// are open-ended (ex: `10:20`, `100:`, `-50:` or `::10`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Slice {
+    start: Option<isize>,
+    stop: Option<isize>,
+    step: usize,
+}
+
+impl Slice {
+    /// Create a `Slice`. A step of 0 is read as 1.
+    pub(crate) fn new(start: Option<isize>, stop: Option<isize>, step: usize) -> Slice {
+        Slice {
+            start,
+            stop,
+            step: step.max(1),
+        }
+    }
+
+    /// Parse a slice. A single index selects one line (ex: `-1` for the last line).
+    fn parse(value: &str) -> Option<Slice> {
+        let index = |s: &str| -> Option<Option<isize>> {
+            let s = s.trim();
+            match s.is_empty() {
+                true => Some(None),
+                false => s.parse().ok().map(Some),
+            }
+        };
+
+        match value.split(':').collect::<Vec<_>>()[..] {
+            [i] => {
+                let i = index(i)??;
+                let stop = match i {
+                    -1 => None,
+                    i => Some(i.checked_add(1)?),
+                };
+                Some(Slice::new(Some(i), stop, 1))
+            }
+            [start, stop] => Some(Slice::new(index(start)?, index(stop)?, 1)),
+            [start, stop, step] => {
+                let step = match step.trim() {
+                    "" => 1,
+                    step => step.parse().ok().filter(|n| *n > 0)?,
+                };
+                Some(Slice::new(index(start)?, index(stop)?, step))
+            }
+            _ => None,
+        }
+    }
+
+    /// Resolve the slice for a file of `len` lines. Return the first line number and the exclusive
+    /// end of the lines selected.
+    fn resolve(&self, len: usize) -> (usize, usize) {
+        let resolve = |i: isize| match i < 0 {
+            true => len.saturating_sub(i.unsigned_abs()),
+            false => i.unsigned_abs().min(len),
+        };
+        let start = self.start.map_or(0, resolve);
+        let stop = self.stop.map_or(len, resolve);
+
+        (start, stop.max(start))
+    }
+
+    /// Check if the slice selects line `ln` of a file of `len` lines.
+    fn contains(&self, ln: usize, len: usize) -> bool {
+        let (start, stop) = self.resolve(len);
+
+        ln >= start && ln < stop && (ln - start).is_multiple_of(self.step)
+    }
+
+    /// Inclusive window of line numbers selected for a file of `len` lines. Return `None` if no
+    /// lines are selected.
+    fn window(&self, len: usize) -> Option<(usize, usize)> {
+        let (start, stop) = self.resolve(len);
+
+        (stop > start).then(|| (start, start + (stop - 1 - start) / self.step * self.step))
+    }
+
+    /// Exclusive end of the lines selected if the slice doesn't depend on the length of the file.
+    fn head_end(&self) -> Option<usize> {
+        match (self.start, self.stop) {
+            (start, Some(stop)) if start.is_none_or(|i| i >= 0) && stop >= 0 => {
+                Some(stop.unsigned_abs())
+            }
+            _ => None,
+        }
+    }
+
+    /// Lines counted back from the end of the file the slice starts at if it only selects lines
+    /// relative to the end.
+    fn tail_len(&self) -> Option<usize> {
+        match (self.start, self.stop) {
+            (Some(start), stop) if start < 0 && stop.is_none_or(|i| i < 0) => {
+                Some(start.unsigned_abs())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Slice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let index = |i: Option<isize>| i.map(|i| i.to_string()).unwrap_or_default();
+
+        write!(f, "{}:{}", index(self.start), index(self.stop))?;
+
+        if self.step != 1 {
+            write!(f, ":{}", self.step)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Lines selected by a list of slices (ex: `10:20,100:,-50:`). A line is selected if any slice
+/// selects it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection(Vec<Slice>);
+
+impl Selection {
+    /// Parse a comma separated list of slices.
+    pub fn parse(value: &str) -> Option<Selection> {
+        value
+            .split(',')
+            .map(Slice::parse)
+            .collect::<Option<_>>()
+            .map(Selection)
+    }
+
+    /// Check if line `ln` of a file of `len` lines is selected.
+    pub(crate) fn contains(&self, ln: usize, len: usize) -> bool {
+        self.0.iter().any(|s| s.contains(ln, len))
+    }
+
+    /// Inclusive window of line numbers selected for a file of `len` lines. Return `None` if no
+    /// lines are selected.
+    fn window(&self, len: usize) -> Option<(usize, usize)> {
+        self.0
+            .iter()
+            .filter_map(|s| s.window(len))
+            .reduce(|(l1, u1), (l2, u2)| (l1.min(l2), u1.max(u2)))
+    }
+
+    /// Exclusive end of the lines selected if no slice depends on the length of the file.
+    fn head_end(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .map(Slice::head_end)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+    }
+
+    /// Lines counted back from the end of the file if every slice only selects lines relative to
+    /// the end.
+    fn tail_len(&self) -> Option<usize> {
+        self.0
+            .iter()
+            .map(Slice::tail_len)
+            .collect::<Option<Vec<_>>>()?
+            .into_iter()
+            .max()
+    }
+
+    /// Keep the selected lines of a file of `len` lines.
+    fn select(&self, lines: EnumeratedLines, len: usize) -> EnumeratedLines {
+        lines
+            .into_iter()
+            .filter(|(ln, _)| self.contains(*ln, len))
+            .collect()
+    }
 }
 
 /// Kinds of evaluation strategies.
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum EvalKind {
     /// Strategy to select all data available from query plan.
     All,
@@ -23,39 +213,102 @@ enum EvalKind {
     Any,
     /// Strategy to select only the latest N data from query plan.
     Latest(usize),
+    /// Strategy to select only the first N data from query plan.
+    First(usize),
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let slices: Vec<String> = self.0.iter().map(|s| s.to_string()).collect();
+
+        write!(f, "{}", slices.join(","))
+    }
 }
 
-/// Instructions for query to execute.
-#[derive(Default)]
-pub(crate) struct Instructions {
-    paths: Vec<PathBuf>,
+/// Instructions for query to execute. Instructions are built by chaining methods, each returning
+/// new `Instructions`.
+#[derive(Clone, Default)]
+pub struct Instructions {
+    sources: Vec<Arc<dyn LineSource>>,
     keywords: Vec<String>,
-    ranges: Vec<RangeKind>,
+    selection: Option<Selection>,
+    date_range: Option<(NaiveDateTime, NaiveDateTime)>,
     evals: Vec<EvalKind>,
+    repeats: Option<Repeats>,
+    sort: Option<LineOrder>,
+    skip: usize,
+    limit: Option<usize>,
+    context: Option<usize>,
+    order: Vec<StageKind>,
+    encoding: Option<Encoding>,
+    rotated: bool,
+    segment_labels: bool,
+    timestamps: Parser,
+    output_tz: Option<Zone>,
+    redactor: Option<Redactor>,
 }
 
 impl Instructions {
     /// Create unpopulated `Instructions`.
-    pub(crate) fn new() -> Instructions {
+    pub fn new() -> Instructions {
         Instructions {
-            paths: vec![],
+            sources: vec![],
             keywords: vec![],
-            ranges: vec![],
+            selection: None,
+            date_range: None,
             evals: vec![],
+            repeats: None,
+            sort: None,
+            skip: 0,
+            limit: None,
+            context: None,
+            order: vec![],
+            encoding: None,
+            rotated: false,
+            segment_labels: false,
+            timestamps: Parser::default(),
+            output_tz: None,
+            redactor: None,
         }
     }
 
     /// Add path to instructions and return new `Instructions`.
-    pub(crate) fn add_path(self, path: PathBuf) -> Instructions {
+    pub fn add_path(self, path: PathBuf) -> Instructions {
+        self.add_source(FileSource::new(path))
+    }
+
+    /// Add a source of lines to instructions and return new `Instructions`. Sources are read in
+    /// the order they were added.
+    pub fn add_source<S: LineSource + 'static>(self, source: S) -> Instructions {
         let mut instructions = self;
 
-        instructions.paths.push(path);
+        instructions.sources.push(Arc::new(source));
 
         instructions
     }
 
-    /// Add keyword to instructions and return new `Instructions`.
-    pub(crate) fn add_keyword(self, word: String) -> Instructions {
+    /// Access sources to query.
+    pub fn sources(&self) -> &[Arc<dyn LineSource>] {
+        &self.sources
+    }
+
+    /// Access keywords to filter for.
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Replace keywords in instructions and return new `Instructions`.
+    pub fn with_keywords(self, words: Vec<String>) -> Instructions {
+        let mut instructions = self;
+
+        instructions.keywords = words;
+
+        instructions
+    }
+
+    /// Add keyword to instructions and return new `Instructions`. Lines are kept if they contain
+    /// every keyword, unless `match_any` is set.
+    pub fn add_keyword(self, word: String) -> Instructions {
         let mut instructions = self;
 
         instructions.keywords.push(word);
@@ -63,214 +316,943 @@ impl Instructions {
         instructions
     }
 
-    /// Add range to instructions and return new `Instructions`.
-    pub(crate) fn add_range(self, name: &str, lower: usize, upper: usize) -> Instructions {
+    /// Add a slice of lines to select and return new `Instructions`. Lines selected by any slice
+    /// are kept.
+    pub(crate) fn add_slice(self, slice: Slice) -> Instructions {
+        let mut instructions = self;
+
+        instructions
+            .selection
+            .get_or_insert_with(Selection::default)
+            .0
+            .push(slice);
+
+        instructions
+    }
+
+    /// Add the slices of a selection to instructions and return new `Instructions`.
+    pub fn add_selection(self, selection: Selection) -> Instructions {
+        selection.0.into_iter().fold(self, Instructions::add_slice)
+    }
+
+    /// Add an inclusive range of zero-based line numbers to instructions and return new
+    /// `Instructions`. Return an error if a line number is too large to be selected.
+    pub fn add_line_range(self, lower: usize, upper: usize) -> Result<Instructions> {
+        let out_of_range =
+            || Error::Args(format!("line-range {} {} is out of range", lower, upper));
+        let start = isize::try_from(lower).map_err(|_| out_of_range())?;
+        let stop = isize::try_from(upper)
+            .ok()
+            .and_then(|upper| upper.checked_add(1))
+            .ok_or_else(out_of_range)?;
+
+        Ok(self.add_slice(Slice::new(Some(start), Some(stop), 1)))
+    }
+
+    /// Add date range to instructions and return new `Instructions`. Lines are kept if their
+    /// timestamp is within the inclusive range.
+    pub fn add_date_range(self, lower: NaiveDateTime, upper: NaiveDateTime) -> Instructions {
         let mut instructions = self;
 
-        let kind = match name {
-            "line-range" => RangeKind::LineRange((lower, upper)),
-            _ => unreachable!(),
+        instructions.date_range = Some((lower, upper));
+
+        instructions
+    }
+
+    /// Select the first `n` lines of each source and return new `Instructions`.
+    pub fn add_head(self, n: usize) -> Instructions {
+        self.add_slice(Slice::new(
+            None,
+            Some(n.min(isize::MAX as usize) as isize),
+            1,
+        ))
+    }
+
+    /// Select the last `n` lines of each source and return new `Instructions`.
+    pub fn add_tail(self, n: usize) -> Instructions {
+        let slice = match n {
+            0 => Slice::new(None, Some(0), 1),
+            n => Slice::new(Some(-(n.min(isize::MAX as usize) as isize)), None, 1),
         };
 
-        instructions.ranges.push(kind);
+        self.add_slice(slice)
+    }
+
+    /// Set how repeated lines are reduced and return new `Instructions`.
+    pub fn with_repeats(self, repeats: Repeats) -> Instructions {
+        let mut instructions = self;
+
+        instructions.repeats = Some(repeats);
 
         instructions
     }
 
-    /// Add relative range to instructions and return new `Instructions`.
-    pub(crate) fn add_relative_range(self, name: &str, val: usize) -> Instructions {
+    /// Set the order lines are sorted in and return new `Instructions`.
+    pub fn with_sort(self, order: LineOrder) -> Instructions {
         let mut instructions = self;
 
-        let kind = match name {
-            "head" => RangeKind::Head(val),
-            "tail" => RangeKind::Tail(val),
-            _ => unreachable!(),
-        };
+        instructions.sort = Some(order);
+
+        instructions
+    }
+
+    /// Set the number of lines to skip after filtering and return new `Instructions`.
+    pub fn with_skip(self, n: usize) -> Instructions {
+        let mut instructions = self;
 
-        instructions.ranges.push(kind);
+        instructions.skip = n;
 
         instructions
     }
 
+    /// Set the number of lines to keep after filtering and skipping and return new
+    /// `Instructions`.
+    pub fn with_limit(self, n: usize) -> Instructions {
+        let mut instructions = self;
+
+        instructions.limit = Some(n);
+
+        instructions
+    }
+
+    /// Set the number of lines displayed around each line kept and return new `Instructions`.
+    pub fn with_context(self, n: usize) -> Instructions {
+        let mut instructions = self;
+
+        instructions.context = Some(n);
+
+        instructions
+    }
+
+    /// Set the order stages are applied in and return new `Instructions`. Stages that aren't
+    /// ordered follow in their default order.
+    pub fn with_order(self, order: Vec<StageKind>) -> Instructions {
+        let mut instructions = self;
+
+        instructions.order = order;
+
+        instructions
+    }
+
+    /// Set the encoding to decode files with and return new `Instructions`. Files are decoded
+    /// as UTF-8 if no encoding is set.
+    pub fn with_encoding(self, encoding: Encoding) -> Instructions {
+        let mut instructions = self;
+
+        instructions.encoding = Some(encoding);
+
+        instructions
+    }
+
+    /// Set whether each path is read with its rotated segments and return new `Instructions`.
+    /// Lines are labelled with their segment if `labels` is true.
+    pub fn with_rotated(self, rotated: bool, labels: bool) -> Instructions {
+        let mut instructions = self;
+
+        instructions.rotated = rotated;
+        instructions.segment_labels = labels;
+
+        instructions
+    }
+
+    /// Set the zone timestamps without an offset are read in and return new `Instructions`.
+    pub fn with_input_tz(self, zone: Zone) -> Instructions {
+        let mut instructions = self;
+
+        instructions.timestamps = instructions.timestamps.with_zone(zone);
+
+        instructions
+    }
+
+    /// Set the strftime format timestamps are parsed with and return new `Instructions`.
+    pub fn with_timestamp_format(self, format: String) -> Instructions {
+        let mut instructions = self;
+
+        instructions.timestamps = instructions.timestamps.with_format(format);
+
+        instructions
+    }
+
+    /// Access how timestamps are read.
+    pub fn timestamps(&self) -> &Parser {
+        &self.timestamps
+    }
+
+    /// Set the zone displayed timestamps are converted to and return new `Instructions`.
+    pub fn with_output_tz(self, zone: Zone) -> Instructions {
+        let mut instructions = self;
+
+        instructions.output_tz = Some(zone);
+
+        instructions
+    }
+
+    /// Set how sensitive values are redacted from displayed lines and return new `Instructions`.
+    pub fn with_redactor(self, redactor: Redactor) -> Instructions {
+        let mut instructions = self;
+
+        instructions.redactor = Some(redactor);
+
+        instructions
+    }
+
+    /// Access how sensitive values are redacted, if they are.
+    pub fn redactor(&self) -> Option<&Redactor> {
+        self.redactor.as_ref()
+    }
+
+    /// Keep lines containing every keyword and return new `Instructions`. This is the default.
+    pub fn match_all(self) -> Instructions {
+        self.add_eval(EvalKind::All)
+    }
+
+    /// Keep lines containing any keyword rather than all of them and return new `Instructions`.
+    pub fn match_any(self) -> Instructions {
+        self.add_eval(EvalKind::Any)
+    }
+
+    /// Keep only the latest `n` lines kept by the query and return new `Instructions`. Lines are
+    /// read from the end of each source.
+    pub fn with_latest(self, n: usize) -> Instructions {
+        self.add_eval(EvalKind::Latest(n))
+    }
+
+    /// Keep only the first `n` lines kept by the query and return new `Instructions`. Sources
+    /// stop being read once they are found.
+    pub fn with_first(self, n: usize) -> Instructions {
+        self.add_eval(EvalKind::First(n))
+    }
+
     /// Add eval to instructions and return new `Instructions`.
-    pub(crate) fn add_eval(self, name: &str, val: Option<usize>) -> Instructions {
+    fn add_eval(self, eval: EvalKind) -> Instructions {
         let mut instructions = self;
 
-        match name {
-            "all" => instructions.evals.push(EvalKind::All),
-            "any" => instructions.evals.push(EvalKind::Any),
-            "latest" => instructions.evals.push(EvalKind::Latest(val.unwrap_or(1))),
-            _ => unreachable!(),
-        };
+        instructions.evals.push(eval);
 
         instructions
     }
 }
 
-pub(crate) struct Query(Instructions);
+/// Query built from `Instructions`, reading each source through a pipeline of stages.
+pub struct Query {
+    instructions: Instructions,
+    filter: Filter,
+    pipeline: Pipeline,
+}
 
 impl Query {
-    /// Build query from `Instructions`.
-    pub(crate) fn build(instructions: Instructions) -> Result<Query> {
-        Ok(Query(instructions))
-    }
+    /// Build query from `Instructions`. Stages are ordered as instructed, and stages without
+    /// arguments are left out.
+    pub fn build(instructions: Instructions) -> Result<Query> {
+        let filter = Filter::new(
+            instructions.keywords.clone(),
+            instructions.evals.contains(&EvalKind::Any),
+            instructions.date_range,
+        )
+        .with_timestamps(instructions.timestamps.clone());
+        let limit = instructions.evals.iter().find_map(|e| match e {
+            EvalKind::Latest(n) => Some(Limit::Latest(*n)),
+            EvalKind::First(n) => Some(Limit::Page {
+                skip: instructions.skip,
+                take: Some(*n),
+            }),
+            _ => None,
+        });
+        let limit = limit.or_else(|| {
+            (instructions.skip > 0 || instructions.limit.is_some()).then_some(Limit::Page {
+                skip: instructions.skip,
+                take: instructions.limit,
+            })
+        });
 
-    /// Access to query `Instructions`.
-    fn instructions(&self) -> &Instructions {
-        &self.0
+        let ordered = &instructions.order;
+        let order = ordered.iter().copied().chain(
+            StageKind::DEFAULT_ORDER
+                .into_iter()
+                .filter(|k| !ordered.contains(k)),
+        );
+
+        let mut stages = vec![];
+
+        for kind in order {
+            let stage = match kind {
+                StageKind::Select => instructions.selection.clone().map(Stage::Select),
+                StageKind::Filter => (!filter.is_empty()).then(|| Stage::Filter(filter.clone())),
+                StageKind::Dedupe => instructions.repeats.clone().map(|repeats| {
+                    Stage::Dedupe(repeats.with_timestamps(instructions.timestamps.clone()))
+                }),
+                StageKind::Sort => instructions.sort.clone().map(|order| {
+                    Stage::Sort(order.with_timestamps(instructions.timestamps.clone()))
+                }),
+                StageKind::Limit => limit.clone().map(Stage::Limit),
+                StageKind::Context => instructions.context.map(Stage::Context),
+            };
+
+            if let Some(stage) = stage {
+                stages.push(stage);
+            }
+        }
+
+        Ok(Query {
+            instructions,
+            filter,
+            pipeline: Pipeline::new(stages),
+        })
     }
 
-    // Filter enumerated lines containing keywords depending on a desired evaulation strategy
-    // (eval). An eval can be "all" or "any".
-    fn filter_with_keywords(
-        &self,
-        lines: &[(usize, String)],
-        keywords: &[String],
-        eval: &EvalKind, // TODO: Use clap-recognizable enum
-    ) -> Result<Vec<(usize, String)>> {
-        // Filter lines for lines that contain any of the keywords indicated by caller.
-        let res = lines
+    /// Build a query reading every line of the sources named in `paths` the way this query reads
+    /// lines. Filters and stages are left out.
+    pub fn unfiltered(&self, paths: Vec<PathBuf>) -> Result<Query> {
+        let instructions = self.instructions();
+        let sources = instructions
+            .sources
             .iter()
-            .filter(|(_, l)| string_contains_vec_elements(l, keywords, eval))
-            .into_iter()
+            .filter(|source| paths.iter().any(|path| path == source.name()))
             .cloned()
             .collect();
 
-        Ok(res)
+        Query::build(Instructions {
+            sources,
+            encoding: instructions.encoding,
+            rotated: instructions.rotated,
+            segment_labels: instructions.segment_labels,
+            timestamps: instructions.timestamps.clone(),
+            output_tz: instructions.output_tz,
+            redactor: instructions.redactor.clone(),
+            ..Instructions::new()
+        })
     }
 
-    /// Filter enumerated lines for line numbers selected by the line range.
-    fn filter_with_line_range(
-        &self,
-        lines: &EnumeratedLines,
-        lower: &usize,
-        upper: &usize,
-    ) -> Result<EnumeratedLines> {
-        // If there aren't any lines to filter correctly return with Ok.
-        if lines.is_empty() {
-            return Ok(lines.to_vec());
+    /// Access to query `Instructions`.
+    fn instructions(&self) -> &Instructions {
+        &self.instructions
+    }
+
+    /// Access how the query reads timestamps.
+    pub fn timestamps(&self) -> &Parser {
+        self.instructions().timestamps()
+    }
+
+    /// Access the ordered stages of the query.
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+
+    /// Get the primary evaluation strategy; defaults to All.
+    fn primary_eval(&self) -> EvalKind {
+        if self.instructions().evals.contains(&EvalKind::Any) {
+            EvalKind::Any
+        } else {
+            EvalKind::All
         }
+    }
 
-        let res = lines
-            .iter()
-            .filter(|(i, _)| {
-                if i >= lower && i <= upper {
-                    return true;
-                }
+    /// Determine the encoding to decode a path with.
+    pub fn encoding(&self, path: &Path) -> Result<Encoding> {
+        Encoding::detect(path, self.instructions().encoding).map_err(|e| e.with_path(path))
+    }
 
-                false
-            })
-            .cloned()
-            .collect();
+    /// Check if a line is within the date range of the query, if one was selected.
+    fn in_date_range(&self, line: &str) -> bool {
+        self.filter.in_date_range(line)
+    }
 
-        Ok(res)
+    /// Check if a line is kept by the keywords of the query.
+    pub fn matches_line(&self, line: &str) -> bool {
+        self.filter.matches_keywords(line)
     }
 
-    /// Apply instructions to enumerated line strings.
+    /// Apply the pipeline to every line read.
     fn filter_lines(&self, lines: Lines) -> Result<EnumeratedLines> {
-        // Create scoped mutable res of enumerated lines to operate on.
-        let mut res = lines.enumerated_lines().to_owned();
+        Ok(self.pipeline.run(lines.into_enumerated_lines()))
+    }
 
-        // Set the primary evaluation strategy; defaults to All.
-        let primary_eval = if self.instructions().evals.contains(&EvalKind::Any) {
-            EvalKind::Any
+    /// Apply the pipeline to lines already selected, and possibly filtered, while reading.
+    fn filter_selected(&self, lines: EnumeratedLines) -> Result<EnumeratedLines> {
+        Ok(self.pipeline.run_selected(lines))
+    }
+
+    /// Access the date range of the query if one was selected.
+    fn date_range(&self) -> Option<(&NaiveDateTime, &NaiveDateTime)> {
+        self.instructions()
+            .date_range
+            .as_ref()
+            .map(|(lower, upper)| (lower, upper))
+    }
+
+    /// Inclusive window of line numbers selected for a file of `len` lines. Return `None` if no
+    /// lines are selected.
+    fn line_window(&self, len: usize) -> Option<(usize, usize)> {
+        match self.pipeline.leading_selection() {
+            Some(selection) => selection.window(len),
+            None => Some((0, len.checked_sub(1)?)),
+        }
+    }
+
+    /// Check if line `ln` of a file of `len` lines is selected.
+    fn is_selected(&self, ln: usize, len: usize) -> bool {
+        self.pipeline
+            .leading_selection()
+            .is_none_or(|s| s.contains(ln, len))
+    }
+
+    /// Check if a block of an index may contain lines kept by the keywords of the query.
+    fn block_may_match(&self, block: &Block) -> bool {
+        let keywords = &self.instructions().keywords;
+
+        match self.primary_eval() {
+            EvalKind::Any => keywords.is_empty() || keywords.iter().any(|k| block.may_contain(k)),
+            _ => keywords.iter().all(|k| block.may_contain(k)),
+        }
+    }
+
+    /// Read lines from a path using a fresh sidecar index. Only blocks that can contain selected
+    /// lines are read.
+    fn read_indexed(&self, path: &PathBuf, index: &Index) -> Result<Lines> {
+        let len = index.len();
+        let (lower, upper) = match self.line_window(len) {
+            Some(window) => window,
+            None => return Ok(Lines::new(vec![])),
+        };
+        // Block timestamps are recognized automatically and indexed as UTC when they have no
+        // offset, so blocks can only be skipped by date when timestamps are read the same way.
+        let date_range = self
+            .date_range()
+            .filter(|_| self.instructions().timestamps == Parser::default());
+        let limit = self.pipeline.read_limit().unwrap_or(usize::MAX);
+
+        let mut reader = buffer::read_file(path)?;
+        let mut buf = vec![];
+        let mut res = Lines::new(vec![]);
+
+        let blocks = index.blocks().iter().filter(|b| {
+            b.overlaps_lines(lower, upper)
+                && date_range.is_none_or(|(l, u)| b.overlaps_time(l, u))
+                && self.block_may_match(b)
+        });
+
+        // Lines are filtered as they're read, so reading stops once the limit is reached.
+        'blocks: for block in blocks {
+            reader.seek(SeekFrom::Start(block.offset()))?;
+
+            for ln in block.lines() {
+                if res.enumerated_lines().len() >= limit {
+                    break 'blocks;
+                }
+                if !buffer::read_raw_line(&mut reader, &mut buf)? {
+                    break;
+                }
+                if ln >= lower && ln <= upper && self.is_selected(ln, len) {
+                    let (line, replaced) = Encoding::Utf8.decode(&buf);
+
+                    if self.filter.keeps(&line) {
+                        res.push(ln, line, replaced);
+                    }
+                }
+            }
+        }
+
+        filter_read(res, |l| self.filter_selected(l.into_enumerated_lines()))
+    }
+
+    /// Read only the lines of a path selected by line number. Selections counting from the start
+    /// of the file stop reading once past their last line, and selections counting from the end
+    /// are read backwards from the end of the file. Other selections read the whole file. Return
+    /// `None` if no lines were selected by number.
+    fn read_selected(&self, path: &PathBuf, encoding: Encoding) -> Result<Option<Lines>> {
+        let selection = match self.pipeline.leading_selection() {
+            Some(selection) => selection,
+            None => return Ok(None),
+        };
+
+        let (lines, len) = if let Some(end) = selection.head_end() {
+            let start = selection.window(end).map_or(end, |(lower, _)| lower);
+            (Lines::read_range(path, start, end, encoding)?, end)
+        } else if let Some(n) = selection.tail_len() {
+            let lines = Lines::read_tail(path, n, encoding)?;
+            let len = lines.enumerated_lines().last().map_or(0, |(ln, _)| ln + 1);
+            (lines, len)
         } else {
-            EvalKind::All
+            let lines = Lines::read(path, encoding)?;
+            let len = lines.enumerated_lines().len();
+            (lines, len)
         };
 
-        // Filter lines for the selected relative line ranges.
-        let relative_range = self.instructions().ranges.iter().find_map(|e| match e {
-            RangeKind::Head(_) => Some(create_relative_usize_range(e, res.len() - 1)),
-            RangeKind::Tail(_) => Some(create_relative_usize_range(e, res.len() - 1)),
-            _ => None,
-        });
+        filter_read(lines, |l| {
+            Ok(selection.select(l.into_enumerated_lines(), len))
+        })
+        .map(Some)
+    }
 
-        if let Some((lower, upper)) = relative_range {
-            res = res[lower..=upper].to_vec()
+    /// Apply instructions to the lines of a source. Large files read in full are filtered on
+    /// `threads` workers. IO errors are reported with the source's name.
+    fn read(&self, source: &dyn LineSource, threads: usize) -> Result<Lines> {
+        let res = match source.file() {
+            Some(path) => self.read_path(&path.to_path_buf(), threads),
+            None => self.read_source(source),
+        };
+        let mut res = res.map_err(|e| e.with_path(source.name()))?;
+
+        // Timestamps are converted for display once lines are kept.
+        if let Some(output) = &self.instructions().output_tz {
+            let timestamps = &self.instructions().timestamps;
+            let lines = res
+                .enumerated_lines()
+                .iter()
+                .map(|(ln, line)| (*ln, timestamps.convert(line, output).into_owned()))
+                .collect();
+
+            res.set_lines(lines);
         }
 
-        // Filter for absolute ranges.
-        let range = self.instructions().ranges.iter().find_map(|e| match e {
-            RangeKind::LineRange((lower, upper)) => Some((lower, upper)),
-            _ => None,
-        });
+        // Sensitive values are redacted from the lines kept, after they were filtered.
+        if let Some(redactor) = self.instructions().redactor() {
+            let lines = res
+                .enumerated_lines()
+                .iter()
+                .map(|(ln, line)| (*ln, redactor.redact(line).into_owned()))
+                .collect();
+
+            res.set_lines(lines);
+        }
 
-        if let Some((lower, upper)) = range {
-            res = self.filter_with_line_range(&res, lower, upper)?;
+        Ok(res)
+    }
+
+    /// Apply instructions to every line of a source that isn't read as a file.
+    fn read_source(&self, source: &dyn LineSource) -> Result<Lines> {
+        let mut bytes = vec![];
+
+        source.open()?.read_to_end(&mut bytes)?;
+
+        let encoding = Encoding::detect_bytes(&bytes, self.instructions().encoding);
+
+        filter_read(encoding.decode_lines(&bytes), |l| self.filter_lines(l))
+    }
+
+    /// Apply instructions to the lines of a path.
+    fn read_path(&self, path: &PathBuf, threads: usize) -> Result<Lines> {
+        if self.instructions().rotated {
+            return filter_read(self.read_rotated(path)?, |l| self.filter_lines(l));
         }
 
-        // Filter keywords from remaining lines.
-        if !self.instructions().keywords.is_empty() {
-            res = self.filter_with_keywords(&res, &self.instructions().keywords, &primary_eval)?;
+        // Compressed files can't be searched or seeked in, so they're read in full.
+        if buffer::is_compressed(path) {
+            return filter_read(self.read_whole(path)?, |l| self.filter_lines(l));
         }
 
-        // Filter for latest N found in remaining lines.
-        let latest = self.instructions().evals.iter().find_map(|e| match e {
-            EvalKind::Latest(n) => Some(n),
-            _ => None,
-        });
+        let encoding = self.encoding(path)?;
 
-        if let Some(n) = latest {
-            if n < &res.len() {
-                res = res[res.len() - n..].to_vec();
+        // Files that can't be split on raw bytes are decoded in full.
+        if !encoding.is_byte_oriented() {
+            return filter_read(encoding.read_lines(path)?, |l| self.filter_lines(l));
+        }
+
+        // Lines are sorted while they're read, so files larger than memory can be sorted.
+        if let Some((i, order)) = self.pipeline.streamed_sort() {
+            return self.read_sorted(path, encoding, i, order);
+        }
+
+        // Stages that can't be applied while reading need every line first.
+        if !self.pipeline.streams() {
+            return filter_read(Lines::read(path, encoding)?, |l| self.filter_lines(l));
+        }
+
+        // Indexes and keyword searches work on raw bytes, so they assume UTF-8.
+        let utf8 = encoding == Encoding::Utf8;
+
+        if let Some(index) = utf8.then(|| Index::load(path)).flatten() {
+            return self.read_indexed(path, &index);
+        }
+
+        if let Some(lines) = self.read_selected(path, encoding)? {
+            return filter_read(lines, |l| self.filter_selected(l.into_enumerated_lines()));
+        }
+
+        let keywords = &self.instructions().keywords;
+        let limit = self.pipeline.read_limit();
+
+        // Without a filter, the first lines read are the lines kept.
+        if let (Some(n), true) = (limit, self.filter.is_empty()) {
+            let lines = Lines::read_range(path, 0, n, encoding)?;
+            return filter_read(lines, |l| self.filter_selected(l.into_enumerated_lines()));
+        }
+
+        if !utf8 || (keywords.is_empty() && self.date_range().is_none()) {
+            return filter_read(Lines::read(path, encoding)?, |l| self.filter_lines(l));
+        }
+
+        // Filter the raw bytes of plain files, only splitting lines around keyword hits.
+        if let Some(map) = buffer::map_file(path)? {
+            let matcher = (!keywords.is_empty())
+                .then(|| Matcher::new(keywords, self.primary_eval() == EvalKind::All));
+            let threads = if map.len() as u64 >= scan::PARALLEL_THRESHOLD {
+                threads
+            } else {
+                1
+            };
+            let keep = |l: &str| self.in_date_range(l);
+            let lines = match limit {
+                Some(n) => scan::filter_bytes_until(&map, matcher.as_ref(), keep, n),
+                None => scan::filter_bytes(&map, threads, matcher.as_ref(), keep),
+            };
+
+            return filter_read(lines, |l| self.filter_selected(l.into_enumerated_lines()));
+        }
+
+        filter_read(Lines::read(path, encoding)?, |l| self.filter_lines(l))
+    }
+
+    /// Read the lines of a path kept by the filter into the sort at position `i` of the pipeline,
+    /// then apply the stages following it. Only the lines the sort holds in memory and the lines
+    /// kept after sorting are held at once.
+    fn read_sorted(
+        &self,
+        path: &PathBuf,
+        encoding: Encoding,
+        i: usize,
+        order: &LineOrder,
+    ) -> Result<Lines> {
+        let mut reader = buffer::read_file(path)?;
+        let mut buf = vec![];
+        let mut sorter = order.sorter();
+        let mut replaced = vec![];
+
+        for ln in 0.. {
+            if !buffer::read_raw_line(&mut reader, &mut buf)? {
+                break;
+            }
+
+            let (line, lossy) = encoding.decode(&buf);
+
+            if self.filter.keeps(&line) {
+                if lossy {
+                    replaced.push(ln);
+                }
+                sorter.push(ln, line)?;
             }
         }
 
-        Ok(res.to_vec())
+        // Stages keeping the first lines sorted stop the merge once they're found.
+        let limit = self.pipeline.sorted_limit(i).unwrap_or(usize::MAX);
+        let sorted = sorter
+            .finish()?
+            .take(limit)
+            .collect::<Result<EnumeratedLines>>()?;
+
+        filter_read(Lines::new(sorted).with_replaced(replaced), |l| {
+            Ok(self.pipeline.run_after(i, l.into_enumerated_lines()))
+        })
     }
 
-    /// Execute query using `Instructions` and print results to stdout.
-    pub(crate) fn execute(&self) -> Result<()> {
-        for (i, path) in self.instructions().paths.iter().enumerate() {
-            let res = Lines::new(self.filter_lines(Lines::read(path)?)?);
-            print!(
-                "\nFile ({}/{}): {}",
-                i + 1,
-                self.instructions().paths.len(),
-                path.display()
-            );
-            println!("{}", res);
+    /// Read and decode every line of a path. Compressed files are decompressed first.
+    fn read_whole(&self, path: &PathBuf) -> Result<Lines> {
+        if !buffer::is_compressed(path) {
+            return Lines::read(path, self.encoding(path)?);
         }
 
-        Ok(())
+        let bytes = buffer::read_compressed(path)?;
+        let encoding = Encoding::detect_bytes(&bytes, self.instructions().encoding);
+
+        Ok(encoding.decode_lines(&bytes))
     }
+
+    /// Read every segment of the rotated set a path belongs to as one file. Segments are read
+    /// oldest first and their lines are numbered continuously.
+    fn read_rotated(&self, path: &Path) -> Result<Lines> {
+        let mut res = Lines::new(vec![]);
+
+        for segment in rotated::segments(path)? {
+            let lines = self
+                .read_whole(&segment)
+                .map_err(|e| e.with_path(&segment))?;
+            let label = self.instructions().segment_labels.then(|| {
+                segment
+                    .file_name()
+                    .unwrap_or(segment.as_os_str())
+                    .to_string_lossy()
+                    .into_owned()
+            });
+
+            res.append(lines, label);
+        }
+
+        Ok(res)
+    }
+
+    /// Apply instructions to each source and pass the resulting `Lines` to `f` with the source's
+    /// position and name. Sources are read concurrently, but `f` is called in order. When several
+    /// sources are queried, sources that fail are reported to stderr and skipped.
+    ///
+    /// Return `Error::Partial` if some paths failed, or `Error::NoMatch` if no path had lines in
+    /// its results.
+    pub fn for_each_path<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &Path, Lines) -> Result<()>,
+    {
+        let total = self.instructions().sources.len();
+        let mut matched = false;
+        let mut failed = 0;
+
+        self.for_each_path_on(scan::workers(), |i, path, res| match res {
+            Ok(lines) => {
+                matched |= !lines.enumerated_lines().is_empty();
+                f(i, path, lines)
+            }
+            // A single path fails the query as before.
+            Err(e) if total == 1 => Err(e),
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+                Ok(())
+            }
+        })?;
+
+        if failed > 0 {
+            Err(Error::Partial { failed, total })
+        } else if !matched {
+            Err(Error::NoMatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Apply instructions to each source using up to `available` threads and pass each source's
+    /// result to `f`. Threads are split between reading sources concurrently and scanning each
+    /// file in chunks.
+    fn for_each_path_on<F>(&self, available: usize, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &Path, Result<Lines>) -> Result<()>,
+    {
+        let sources = &self.instructions().sources;
+        let workers = available.min(sources.len()).max(1);
+        let threads = (available / workers).max(1);
+
+        if workers == 1 {
+            for (i, source) in sources.iter().enumerate() {
+                f(i, source.name(), self.read(source.as_ref(), threads))?;
+            }
+
+            return Ok(());
+        }
+
+        let next = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            let (tx, rx) = mpsc::channel();
+
+            for _ in 0..workers {
+                let (tx, next) = (tx.clone(), &next);
+
+                s.spawn(move || loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+
+                    if i >= sources.len() {
+                        break;
+                    }
+
+                    let res = self.read(sources[i].as_ref(), threads);
+
+                    if tx.send((i, res)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(tx);
+
+            // Hold results that finish early until the paths before them are done.
+            let mut pending = BTreeMap::new();
+            let mut done = 0;
+
+            for (i, res) in rx {
+                pending.insert(i, res);
+
+                while let Some(res) = pending.remove(&done) {
+                    f(done, sources[done].name(), res)?;
+                    done += 1;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Display the header for the path at position `i`.
+    pub fn path_header(&self, i: usize, path: &Path) -> String {
+        sink::header(i, self.instructions().sources.len(), path)
+    }
+
+    /// Describe the lines of a path's results that had invalid bytes replaced, if any.
+    pub fn replaced_report(&self, path: &Path, lines: &Lines) -> Option<String> {
+        let replaced = lines.replaced();
+
+        if replaced.is_empty() {
+            return None;
+        }
+
+        let mut lns: Vec<String> = replaced
+            .iter()
+            .take(REPORTED_LINES)
+            .map(|ln| format!("ln{}", ln))
+            .collect();
+
+        if replaced.len() > REPORTED_LINES {
+            lns.push(format!("and {} more", replaced.len() - REPORTED_LINES));
+        }
+
+        Some(format!(
+            "{}: {} {} had invalid bytes replaced with U+FFFD: {}",
+            path.display(),
+            replaced.len(),
+            if replaced.len() == 1 { "line" } else { "lines" },
+            lns.join(", ")
+        ))
+    }
+
+    /// Iterate over the lines kept from each source, one source after another. Sources are read
+    /// as the iterator reaches them, and a source that fails yields its error before the next
+    /// source is read.
+    pub fn matches(&self) -> Matches<'_> {
+        Matches {
+            query: self,
+            next: 0,
+            lines: vec![].into_iter(),
+        }
+    }
+
+    /// Execute query using `Instructions` and write the results of each source to `sink`.
+    /// Sources are reported like `for_each_path` reports paths.
+    pub fn execute<S: Sink>(&self, sink: &mut S) -> Result<()> {
+        let total = self.instructions().sources.len();
+
+        self.for_each_path(|i, path, res| {
+            let report = self.replaced_report(path, &res);
+
+            sink.write(i, total, path, &Match::from_lines(i, path, res))?;
+
+            if let Some(report) = report {
+                eprintln!("{}", report);
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// A line kept by a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Match {
+    source: usize,
+    name: PathBuf,
+    ln: usize,
+    line: String,
+    segment: Option<String>,
+    replaced: bool,
 }
 
-/// Check string for "any" or "all" (eval) elements from vector. Return true of eval is met,
-/// otherwise return false.
-fn string_contains_vec_elements(string: &str, vec: &[String], eval: &EvalKind) -> bool {
-    match eval {
-        EvalKind::All => vec.iter().all(|e| string.contains(e)),
-        EvalKind::Any => vec.iter().any(|e| string.contains(e)),
-        _ => false,
+impl Match {
+    /// Create a `Match` for each line of a source's results.
+    fn from_lines(source: usize, name: &Path, lines: Lines) -> Vec<Match> {
+        let replaced: HashSet<usize> = lines.replaced().iter().copied().collect();
+        let segments: Vec<Option<String>> = lines
+            .enumerated_lines()
+            .iter()
+            .map(|(ln, _)| lines.segment_label(*ln).map(str::to_string))
+            .collect();
+
+        lines
+            .into_enumerated_lines()
+            .into_iter()
+            .zip(segments)
+            .map(|((ln, line), segment)| Match {
+                source,
+                name: name.to_path_buf(),
+                ln,
+                line,
+                segment,
+                replaced: replaced.contains(&ln),
+            })
+            .collect()
+    }
+
+    /// Position of the source the line was read from in the `Instructions`.
+    pub fn source(&self) -> usize {
+        self.source
+    }
+
+    /// Path or name of the source the line was read from.
+    pub fn name(&self) -> &Path {
+        &self.name
+    }
+
+    /// Number of the line in its source, counting from 0.
+    pub fn ln(&self) -> usize {
+        self.ln
+    }
+
+    /// The line, with timestamps converted and values redacted as instructed.
+    pub fn line(&self) -> &str {
+        &self.line
+    }
+
+    /// Label of the rotated segment the line was read from, if segments are labelled.
+    pub fn segment(&self) -> Option<&str> {
+        self.segment.as_deref()
+    }
+
+    /// Check if the line had invalid bytes replaced with U+FFFD when it was decoded.
+    pub fn is_replaced(&self) -> bool {
+        self.replaced
     }
 }
 
-/// Create an inclusive usize range of lower and upper values relative to max values and kind of
-/// range.
-fn create_relative_usize_range(kind: &RangeKind, max: usize) -> (usize, usize) {
-    match kind {
-        RangeKind::Head(n) => {
-            if *n - 1 > max {
-                (0, max)
-            } else {
-                (0, *n - 1)
+/// Iterator over the lines kept by a `Query`, created by `Query::matches`.
+pub struct Matches<'a> {
+    query: &'a Query,
+    next: usize,
+    lines: vec::IntoIter<Match>,
+}
+
+impl Iterator for Matches<'_> {
+    type Item = Result<Match>;
+
+    fn next(&mut self) -> Option<Result<Match>> {
+        loop {
+            if let Some(m) = self.lines.next() {
+                return Some(Ok(m));
             }
-        }
-        RangeKind::Tail(n) => {
-            if *n > max {
-                (0, max)
-            } else {
-                (max - *n + 1, max)
+
+            let i = self.next;
+            let source = self.query.instructions().sources.get(i)?;
+            self.next += 1;
+
+            match self.query.read(source.as_ref(), scan::workers()) {
+                Ok(lines) => self.lines = Match::from_lines(i, source.name(), lines).into_iter(),
+                Err(e) => return Some(Err(e)),
             }
         }
-        _ => (0, max),
     }
 }
 
+/// Apply `filter` to lines read from a path. Replaced line numbers are kept for lines that
+/// remain.
+fn filter_read<F>(lines: Lines, filter: F) -> Result<Lines>
+where
+    F: FnOnce(Lines) -> Result<EnumeratedLines>,
+{
+    let replaced = lines.replaced().to_vec();
+    let segments = lines.segments().to_vec();
+
+    Ok(Lines::new(filter(lines)?)
+        .with_replaced(replaced)
+        .with_segments(segments))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{source::MemorySource, test_dir::TestDir};
+    use std::fs;
 
     #[test]
     fn add_paths_to_instruction() {
@@ -278,7 +1260,8 @@ mod tests {
         let instructions = Instructions::new()
             .add_path(paths[0].clone())
             .add_path(paths[1].clone());
-        assert_eq!(instructions.paths, paths);
+        let names: Vec<&Path> = instructions.sources.iter().map(|s| s.name()).collect();
+        assert_eq!(names, paths);
     }
 
     #[test]
@@ -294,19 +1277,41 @@ mod tests {
     fn add_ranges_to_instructions() {
         let ranges: Vec<(usize, usize)> = vec![(0, 0), (1, 2)];
         let instructions = Instructions::new()
-            .add_range("line-range", ranges[0].0, ranges[0].1)
-            .add_range("line-range", ranges[1].0, ranges[1].1);
-        assert_eq!(instructions.ranges.len(), ranges.len());
+            .add_line_range(ranges[0].0, ranges[0].1)
+            .unwrap()
+            .add_line_range(ranges[1].0, ranges[1].1)
+            .unwrap();
+        assert_eq!(instructions.selection.unwrap().0.len(), ranges.len());
+
+        // Line numbers that can't be selected are rejected rather than wrapping.
+        for (lower, upper) in [
+            (0, usize::MAX),
+            (usize::MAX, usize::MAX),
+            (0, isize::MAX as usize),
+        ] {
+            assert!(matches!(
+                Instructions::new().add_line_range(lower, upper),
+                Err(Error::Args(_))
+            ));
+        }
     }
 
     #[test]
     fn add_eval_to_instructions() {
-        let evals = vec!["all", "any", "latest"];
         let instructions = Instructions::new()
-            .add_eval(evals[0].clone(), None)
-            .add_eval(evals[1].clone(), None)
-            .add_eval(evals[2].clone(), Some(2));
-        assert_eq!(instructions.evals.len(), evals.len());
+            .match_all()
+            .match_any()
+            .with_latest(2)
+            .with_first(3);
+        assert_eq!(
+            instructions.evals,
+            [
+                EvalKind::All,
+                EvalKind::Any,
+                EvalKind::Latest(2),
+                EvalKind::First(3)
+            ]
+        );
     }
 
     #[test]
@@ -359,7 +1364,7 @@ mod tests {
         let instructions = Instructions::new()
             .add_keyword("target".to_string())
             .add_keyword("line".to_string())
-            .add_eval("any", None);
+            .match_any();
         let query = Query::build(instructions);
         let lines = Lines::new(
             vec![
@@ -386,7 +1391,7 @@ mod tests {
 
     #[test]
     fn execute_query_with_line_range() {
-        let instructions = Instructions::new().add_range("line-range", 1, 2);
+        let instructions = Instructions::new().add_line_range(1, 2).unwrap();
         let query = Query::build(instructions);
         let lines = Lines::new(
             vec![
@@ -413,7 +1418,7 @@ mod tests {
 
     #[test]
     fn execute_query_with_latest_overrided() {
-        let instructions = Instructions::new().add_eval("latest", Some(2));
+        let instructions = Instructions::new().with_latest(2);
         let query = Query::build(instructions);
         let lines = Lines::new(
             vec![
@@ -440,7 +1445,7 @@ mod tests {
 
     #[test]
     fn execute_query_with_head() {
-        let instructions = Instructions::new().add_relative_range("head", 1);
+        let instructions = Instructions::new().add_head(1);
         let query = Query::build(instructions);
         let lines = Lines::new(
             vec![
@@ -464,9 +1469,131 @@ mod tests {
         }
     }
 
+    #[test]
+    fn for_each_path_in_order() {
+        let dir = TestDir::new("query-paths");
+        let mut instructions = Instructions::new().add_keyword("target".to_string());
+
+        for i in 0..3 {
+            let path = dir.join(format!("{}.log", i));
+            fs::write(&path, format!("line\ntarget {}\n", i)).unwrap();
+            instructions = instructions.add_path(path);
+        }
+
+        let query = Query::build(instructions).unwrap();
+        let mut res = vec![];
+
+        query
+            .for_each_path_on(4, |i, _, lines| {
+                res.push((i, lines?.enumerated_lines().to_owned()));
+                Ok(())
+            })
+            .unwrap();
+
+        assert_eq!(res.len(), 3);
+        for (i, (j, lines)) in res.iter().enumerate() {
+            assert_eq!(i, *j);
+            assert_eq!(lines, &vec![(1, format!("target {}", i))]);
+        }
+    }
+
+    #[test]
+    fn read_lines_with_invalid_bytes() {
+        let dir = TestDir::new("query-invalid");
+        let path = dir.join("app.log");
+        fs::write(&path, b"first\nbad \xff target\nlast target\n").unwrap();
+
+        let query = Query::build(Instructions::new().add_keyword("target".to_string())).unwrap();
+        let res = query.read(&FileSource::new(path), 1).unwrap();
+
+        assert_eq!(
+            res.enumerated_lines(),
+            &vec![
+                (1, "bad \u{fffd} target".to_string()),
+                (2, "last target".to_string())
+            ]
+        );
+        assert_eq!(res.replaced(), &[1]);
+    }
+
+    #[test]
+    fn execute_query_without_matches() {
+        let dir = TestDir::new("query-no-match");
+        let path = dir.join("app.log");
+        fs::write(&path, "first\nsecond\n").unwrap();
+
+        let query = Query::build(
+            Instructions::new()
+                .add_path(path)
+                .add_keyword("target".to_string()),
+        )
+        .unwrap();
+
+        assert!(matches!(query.execute(&mut vec![]), Err(Error::NoMatch)));
+    }
+
+    #[test]
+    fn iterate_matches_across_sources() {
+        let text = |name: &str, text: &str| {
+            MemorySource::new(PathBuf::from(name), text.as_bytes().to_vec())
+        };
+        let dir = TestDir::new("query-sources");
+        let query = Query::build(
+            Instructions::new()
+                .add_source(text("api.log", "start\ntarget api\n"))
+                .add_path(dir.join("missing.log"))
+                .add_source(text("db.log", "target db\nend\n"))
+                .add_keyword("target".to_string()),
+        )
+        .unwrap();
+        let matches: Vec<Result<Match>> = query.matches().collect();
+
+        assert_eq!(matches.len(), 3);
+        assert!(matches!(matches[1], Err(Error::Io { path: Some(_), .. })));
+
+        let first = matches[0].as_ref().unwrap();
+        assert_eq!(
+            (first.source(), first.name(), first.ln(), first.line()),
+            (0, Path::new("api.log"), 1, "target api")
+        );
+
+        let last = matches[2].as_ref().unwrap();
+        assert_eq!((last.source(), last.ln(), last.line()), (2, 0, "target db"));
+        assert!(!last.is_replaced());
+    }
+
+    #[test]
+    fn continue_past_failed_paths() {
+        let dir = TestDir::new("query-failed");
+        let path = dir.join("readable.log");
+        fs::write(&path, "target\n").unwrap();
+
+        let query = Query::build(
+            Instructions::new()
+                .add_path(dir.join("missing.log"))
+                .add_path(path),
+        )
+        .unwrap();
+        let mut read = vec![];
+
+        let res = query.for_each_path(|i, _, _| {
+            read.push(i);
+            Ok(())
+        });
+
+        assert_eq!(read, vec![1]);
+        assert!(matches!(
+            res,
+            Err(Error::Partial {
+                failed: 1,
+                total: 2
+            })
+        ));
+    }
+
     #[test]
     fn execute_query_with_tail() {
-        let instructions = Instructions::new().add_relative_range("tail", 1);
+        let instructions = Instructions::new().add_tail(1);
         let query = Query::build(instructions);
         let lines = Lines::new(
             vec![
@@ -489,4 +1616,76 @@ mod tests {
             panic!("test failed");
         }
     }
+
+    #[test]
+    fn number_tail_lines() {
+        let dir = TestDir::new("query-tail");
+        let path = dir.join("app.log");
+        let text: String = (0..20_000).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, text).unwrap();
+
+        let tail = || Lines::read_tail(&path, 2, Encoding::Utf8).unwrap();
+        let numbers = |lines: &Lines| -> Vec<(usize, String)> {
+            assert!(lines.segments().is_empty());
+            lines.enumerated_lines().to_owned()
+        };
+        let expected = vec![
+            (19_998, "line 19998".to_string()),
+            (19_999, "line 19999".to_string()),
+        ];
+        let query_tail = |instructions: Instructions| -> Vec<usize> {
+            let query = Query::build(instructions).unwrap();
+            let lines = query.read(&FileSource::new(path.clone()), 1).unwrap();
+
+            lines.enumerated_lines().iter().map(|(ln, _)| *ln).collect()
+        };
+        let queries = || {
+            for instructions in [
+                Instructions::new().add_tail(3),
+                Instructions::new().add_selection(Selection::parse("-3:").unwrap()),
+            ] {
+                assert_eq!(query_tail(instructions), vec![19_997, 19_998, 19_999]);
+            }
+        };
+
+        // Tails far past the start of the file are numbered from the start of the file, with or
+        // without an index.
+        assert!(fs::metadata(&path).unwrap().len() > 64 * 1024);
+        assert_eq!(numbers(&tail()), expected);
+        queries();
+
+        Index::build(&path).unwrap().write(&path).unwrap();
+        assert_eq!(numbers(&tail()), expected);
+        queries();
+
+        fs::write(&path, "first\nsecond\nthird\n").unwrap();
+        assert_eq!(
+            numbers(&tail()),
+            vec![(1, "second".to_string()), (2, "third".to_string())]
+        );
+    }
+
+    #[test]
+    fn read_lines_selected_by_slices() {
+        let dir = TestDir::new("query-slices");
+        let path = dir.join("app.log");
+        let text: String = (0..30).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, text).unwrap();
+
+        let read = |value: &str| -> Vec<usize> {
+            let selection = Selection::parse(value).unwrap();
+            let query = Query::build(Instructions::new().add_selection(selection)).unwrap();
+            let lines = query.read(&FileSource::new(path.clone()), 1).unwrap();
+
+            lines.enumerated_lines().iter().map(|(ln, _)| *ln).collect()
+        };
+
+        assert_eq!(read("2:4,27:"), vec![2, 3, 27, 28, 29]);
+        assert_eq!(read("-3:"), vec![27, 28, 29]);
+        assert_eq!(read("-5:-3,0"), vec![0, 25, 26]);
+        assert_eq!(read("::10"), vec![0, 10, 20]);
+        assert_eq!(read("5:12:3"), vec![5, 8, 11]);
+        assert_eq!(read("-1"), vec![29]);
+        assert_eq!(read("20:10"), Vec::<usize>::new());
+    }
 }
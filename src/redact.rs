@@ -0,0 +1,517 @@
+use crate::{buffer, encoding::Encoding, error::Error, Result};
+use hmac::{Hmac, Mac};
+use regex::{Regex, RegexSet};
+use sha2::Sha256;
+use std::{
+    borrow::Cow,
+    collections::hash_map::RandomState,
+    fmt, fs,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{BufWriter, Write},
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+};
+
+/// Built-in detectors as names and patterns. Values are the first capture group of a pattern,
+/// or its whole match if it has no groups.
+const DETECTORS: [(&str, &str); 5] = [
+    (
+        "email",
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+    ),
+    (
+        "ip",
+        r"\b(?:(?:25[0-5]|2[0-4]\d|1?\d?\d)\.){3}(?:25[0-5]|2[0-4]\d|1?\d?\d)\b|\b(?:[0-9A-Fa-f]{1,4}:){7}[0-9A-Fa-f]{1,4}\b|\b(?:[0-9A-Fa-f]{1,4}:){1,6}:[0-9A-Fa-f]{1,4}(?::[0-9A-Fa-f]{1,4})*\b",
+    ),
+    ("card", r"\b(?:\d[ -]?){12,18}\d\b"),
+    ("token", r"(?i)\bbearer\s+([A-Za-z0-9._~+/-]+=*)"),
+    (
+        "user",
+        r#"(?i)\b(?:user_?id|uid|user(?:_?name)?)[=:]\s*["']?([^\s"',&;]+)"#,
+    ),
+];
+
+/// Environment variable read for the key values are hashed with.
+pub const KEY_VARIABLE: &str = "LOG_CLI_REDACT_KEY";
+
+/// Extension added to the file names of redacted copies.
+const REDACTED_EXTENSION: &str = "redacted";
+
+/// Hex characters of a keyed hash kept in hashed tokens.
+const TOKEN_LEN: usize = 12;
+
+/// How redacted values are replaced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Replacement {
+    /// The name of the rule matched (ex: <email>).
+    #[default]
+    Mask,
+    /// The name of the rule matched and a keyed hash of the value (ex: <email:3fa2b1c9d0e4>), so
+    /// equal values get equal tokens.
+    Hash,
+    /// A value derived from a keyed hash with the same shape, keeping separators and the class of
+    /// each character.
+    Fake,
+}
+
+impl Replacement {
+    /// Parse a replacement name.
+    pub fn parse(name: &str) -> Option<Replacement> {
+        match name.to_ascii_lowercase().as_str() {
+            "mask" => Some(Replacement::Mask),
+            "hash" => Some(Replacement::Hash),
+            "fake" => Some(Replacement::Fake),
+            _ => None,
+        }
+    }
+}
+
+/// A named pattern of values to redact.
+#[derive(Clone, Debug)]
+struct Rule {
+    name: String,
+    regex: Regex,
+}
+
+impl Rule {
+    /// Find the spans of values to redact in a line.
+    fn spans(&self, line: &str) -> Vec<(usize, usize)> {
+        self.regex
+            .captures_iter(line)
+            .filter_map(|captures| captures.get(1).or_else(|| captures.get(0)))
+            .filter(|m| !m.is_empty())
+            .filter(|m| self.name != "card" || is_card_number(m.as_str()))
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+}
+
+/// Replaces sensitive values in lines. Rules from rules files are applied before the built-in
+/// detectors, so where matches overlap the match of the rule applied first is replaced.
+#[derive(Clone, Debug)]
+pub struct Redactor {
+    rules: Vec<Rule>,
+    set: RegexSet,
+    replacement: Replacement,
+    key: Vec<u8>,
+}
+
+impl Redactor {
+    /// Create a `Redactor` using the built-in detectors. Without a key, values are hashed with a
+    /// random key, so tokens only match within a run.
+    pub fn new(replacement: Replacement) -> Redactor {
+        let rules = DETECTORS
+            .iter()
+            .map(|(name, pattern)| Rule {
+                name: name.to_string(),
+                regex: Regex::new(pattern).expect("detector patterns are valid"),
+            })
+            .collect();
+        let key = RandomState::new().build_hasher().finish().to_le_bytes();
+
+        Redactor::from_rules(rules, replacement, key.to_vec())
+    }
+
+    fn from_rules(rules: Vec<Rule>, replacement: Replacement, key: Vec<u8>) -> Redactor {
+        let set =
+            RegexSet::new(rules.iter().map(|r| r.regex.as_str())).expect("rule patterns are valid");
+
+        Redactor {
+            rules,
+            set,
+            replacement,
+            key,
+        }
+    }
+
+    /// Set the key values are hashed with and return a new `Redactor`.
+    pub fn with_key(self, key: &str) -> Redactor {
+        let mut redactor = self;
+
+        redactor.key = key.as_bytes().to_vec();
+
+        redactor
+    }
+
+    /// Add the rules of a rules file and return a new `Redactor`. Each line is a regex, optionally
+    /// named with a `name = ` prefix. Blank lines and lines starting with `#` are skipped.
+    pub fn with_rules_file(self, path: &Path) -> Result<Redactor> {
+        let text = fs::read_to_string(path).map_err(|e| Error::from(e).with_path(path))?;
+        let mut rules = vec![];
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (name, pattern) = match line.split_once(" = ") {
+                Some((name, pattern)) => (name.trim(), pattern.trim()),
+                None => ("custom", line),
+            };
+            let regex = Regex::new(pattern).map_err(|e| {
+                Error::Parse(format!(
+                    "{}:{}: invalid rule {}: {}",
+                    path.display(),
+                    i + 1,
+                    name,
+                    e
+                ))
+            })?;
+
+            rules.push(Rule {
+                name: name.to_string(),
+                regex,
+            });
+        }
+
+        rules.extend(self.rules);
+
+        Ok(Redactor::from_rules(rules, self.replacement, self.key))
+    }
+
+    /// Find the spans of values to redact in a line, with the rule each matched. Spans are in
+    /// order and don't overlap, and overlapping matches are resolved in the order of the rules.
+    fn spans(&self, line: &str) -> Vec<(usize, usize, usize)> {
+        let mut spans: Vec<(usize, usize, usize)> = self
+            .set
+            .matches(line)
+            .into_iter()
+            .flat_map(|i| {
+                self.rules[i]
+                    .spans(line)
+                    .into_iter()
+                    .map(move |(start, end)| (start, end, i))
+            })
+            .collect();
+        spans.sort_by_key(|(start, _, i)| (*i, *start));
+
+        // Spans of earlier rules are kept first, so later rules only redact what's left.
+        let mut kept: Vec<(usize, usize, usize)> = vec![];
+        for span in spans {
+            if kept.iter().all(|k| span.1 <= k.0 || span.0 >= k.1) {
+                kept.push(span);
+            }
+        }
+        kept.sort_by_key(|(start, _, _)| *start);
+
+        kept
+    }
+
+    /// Replace the values of spans found in a line.
+    fn replace(&self, line: &str, spans: &[(usize, usize, usize)]) -> String {
+        let mut res = String::with_capacity(line.len());
+        let mut last = 0;
+
+        for (start, end, i) in spans {
+            res.push_str(&line[last..*start]);
+            res.push_str(&self.replacement_of(&self.rules[*i].name, &line[*start..*end]));
+            last = *end;
+        }
+        res.push_str(&line[last..]);
+
+        res
+    }
+
+    /// Redact the sensitive values of a line.
+    pub fn redact<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let spans = self.spans(line);
+
+        match spans.is_empty() {
+            true => Cow::Borrowed(line),
+            false => Cow::Owned(self.replace(line, &spans)),
+        }
+    }
+
+    /// Replacement of a value matched by the rule `name`.
+    fn replacement_of(&self, name: &str, value: &str) -> String {
+        match self.replacement {
+            Replacement::Mask => format!("<{}>", name),
+            Replacement::Hash => {
+                let hex: String = self
+                    .hash(value, 0)
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+
+                format!("<{}:{}>", name, &hex[..TOKEN_LEN])
+            }
+            Replacement::Fake => self.fake(value),
+        }
+    }
+
+    /// Keyed hash of a value. Rounds give further bytes for values longer than a hash.
+    fn hash(&self, value: &str, round: u8) -> Vec<u8> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("keys of any size are valid");
+
+        mac.update(&[round]);
+        mac.update(value.as_bytes());
+
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Fake a value with the same shape. IPv4 addresses stay valid addresses.
+    fn fake(&self, value: &str) -> String {
+        let mut bytes = vec![];
+        let mut round = 0;
+
+        while bytes.len() < value.len().max(4) {
+            bytes.extend(self.hash(value, round));
+            round = round.wrapping_add(1);
+        }
+
+        if value.parse::<Ipv4Addr>().is_ok() {
+            return Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string();
+        }
+
+        value
+            .chars()
+            .zip(bytes)
+            .map(|(c, b)| match c {
+                '0'..='9' => (b'0' + b % 10) as char,
+                'a'..='z' => (b'a' + b % 26) as char,
+                'A'..='Z' => (b'A' + b % 26) as char,
+                c => c,
+            })
+            .collect()
+    }
+
+    /// Write a redacted copy of every line of a file to `output`. Compressed files are
+    /// decompressed, and copies are written as UTF-8.
+    pub fn write_copy(
+        &self,
+        path: &Path,
+        output: &Path,
+        encoding: Option<Encoding>,
+    ) -> Result<Redacted> {
+        let mut res = Redacted {
+            path: path.to_path_buf(),
+            output: output.to_path_buf(),
+            lines: 0,
+            counts: vec![0; self.rules.len()],
+            names: self.rules.iter().map(|r| r.name.clone()).collect(),
+        };
+        let mut writer =
+            BufWriter::new(File::create(output).map_err(|e| Error::from(e).with_path(output))?);
+        let mut write = |line: &str| -> Result<()> {
+            let spans = self.spans(line);
+
+            for (_, _, i) in spans.iter() {
+                res.counts[*i] += 1;
+            }
+            res.lines += (!spans.is_empty()) as usize;
+
+            match spans.is_empty() {
+                true => writeln!(writer, "{}", line)?,
+                false => writeln!(writer, "{}", self.replace(line, &spans))?,
+            }
+
+            Ok(())
+        };
+
+        if buffer::is_compressed(path) {
+            let bytes = buffer::read_compressed(path)?;
+            let lines = Encoding::detect_bytes(&bytes, encoding).decode_lines(&bytes);
+
+            for (_, line) in lines.enumerated_lines() {
+                write(line)?;
+            }
+        } else {
+            let encoding = Encoding::detect(path, encoding)?;
+
+            if encoding.is_byte_oriented() {
+                let mut reader = buffer::read_file(path)?;
+                let mut buf = vec![];
+
+                while let Some(line) = buffer::read_line(&mut reader, &mut buf, encoding)? {
+                    write(&line)?;
+                }
+            } else {
+                for (_, line) in encoding.read_lines(path)?.enumerated_lines() {
+                    write(line)?;
+                }
+            }
+        }
+
+        writer.flush()?;
+
+        Ok(res)
+    }
+}
+
+/// Path a redacted copy of a file is written to. Copies are written next to their file unless a
+/// directory is given, and compressed files are copied without their compression extension.
+pub fn copy_path(path: &Path, dir: Option<&Path>) -> PathBuf {
+    let name = match buffer::is_compressed(path) {
+        true => path.file_stem(),
+        false => path.file_name(),
+    };
+    let mut name = name.unwrap_or(path.as_os_str()).to_os_string();
+
+    name.push(".");
+    name.push(REDACTED_EXTENSION);
+
+    match dir {
+        Some(dir) => dir.join(name),
+        None => path.with_file_name(name),
+    }
+}
+
+/// Values redacted in a copy of a file.
+pub struct Redacted {
+    path: PathBuf,
+    output: PathBuf,
+    lines: usize,
+    counts: Vec<usize>,
+    names: Vec<String>,
+}
+
+impl fmt::Display for Redacted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut counts: Vec<(&str, usize)> = vec![];
+
+        // Rules can share names, so counts are merged by name.
+        for (name, count) in self.names.iter().zip(self.counts.iter()) {
+            match counts.iter_mut().find(|(n, _)| n == name) {
+                Some((_, total)) => *total += count,
+                None => counts.push((name, *count)),
+            }
+        }
+
+        let total: usize = self.counts.iter().sum();
+        let counts: Vec<String> = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(name, count)| format!("{} {}", name, count))
+            .collect();
+
+        write!(
+            f,
+            "Redacted {}: {} {} in {} {}",
+            self.path.display(),
+            total,
+            if total == 1 { "value" } else { "values" },
+            self.lines,
+            if self.lines == 1 { "line" } else { "lines" }
+        )?;
+
+        if !counts.is_empty() {
+            write!(f, " ({})", counts.join(", "))?;
+        }
+
+        write!(f, " ({})", self.output.display())
+    }
+}
+
+/// Check if digits pass the Luhn checksum used by card numbers.
+fn is_card_number(value: &str) -> bool {
+    let digits: Vec<u32> = value.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match i % 2 {
+            1 if d * 2 > 9 => d * 2 - 9,
+            1 => d * 2,
+            _ => *d,
+        })
+        .sum();
+
+    (13..=19).contains(&digits.len()) && sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn redact_built_in_values() {
+        let line = "07:00:01 login user_id=42 from 10.0.0.7 mail=bob@example.com \
+            card=4111 1111 1111 1111 order=1234567890123 auth=Bearer abc.def-1";
+        let redactor = Redactor::new(Replacement::Mask);
+
+        assert_eq!(
+            redactor.redact(line),
+            "07:00:01 login user_id=<user> from <ip> mail=<email> \
+            card=<card> order=1234567890123 auth=Bearer <token>"
+        );
+        assert!(matches!(redactor.redact("nothing here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn replace_with_hashes_and_fakes() {
+        let hashed = Redactor::new(Replacement::Hash).with_key("secret");
+        let a = hashed.redact("from 10.0.0.7");
+        let b = hashed.redact("to 10.0.0.7");
+
+        assert_eq!(a[5..], b[3..]);
+        assert!(a.starts_with("from <ip:") && a.len() == "from <ip:>".len() + TOKEN_LEN);
+        assert_ne!(
+            Redactor::new(Replacement::Hash)
+                .with_key("other")
+                .redact("from 10.0.0.7"),
+            a
+        );
+
+        let faked = Redactor::new(Replacement::Fake).with_key("secret");
+        let line = faked.redact("ip=10.0.0.7 mail=Bob.Smith@example.com");
+        let (ip, mail) = line.split_once(' ').unwrap();
+
+        assert!(ip[3..].parse::<Ipv4Addr>().is_ok());
+        assert_ne!(mail, "mail=Bob.Smith@example.com");
+        assert_eq!(mail.len(), "mail=Bob.Smith@example.com".len());
+        assert!(mail[5..].starts_with(char::is_uppercase) && mail.contains('@'));
+        assert_eq!(faked.redact("ip=10.0.0.7 x"), faked.redact("ip=10.0.0.7 x"));
+    }
+
+    #[test]
+    fn add_rules_from_file() {
+        let dir = TestDir::new("rules");
+        let path = dir.join("rules.txt");
+        fs::write(
+            &path,
+            "# account numbers\naccount = acct-(\\d+)\nsession-\\w+\n",
+        )
+        .unwrap();
+
+        let redactor = Redactor::new(Replacement::Mask)
+            .with_rules_file(&path)
+            .unwrap();
+        assert_eq!(
+            redactor.redact("acct-991 session-x1 user=me"),
+            "acct-<account> <custom> user=<user>"
+        );
+
+        // Rules file patterns win over built-in detectors they overlap, even when the detector
+        // match starts first.
+        fs::write(&path, "domain = @(example\\.com)\n").unwrap();
+        let redactor = Redactor::new(Replacement::Mask)
+            .with_rules_file(&path)
+            .unwrap();
+        assert_eq!(
+            redactor.redact("mail=bob@example.com from 10.0.0.7"),
+            "mail=bob@<domain> from <ip>"
+        );
+
+        fs::write(&path, "broken = (\n").unwrap();
+        assert!(Redactor::new(Replacement::Mask)
+            .with_rules_file(&path)
+            .is_err());
+    }
+
+    #[test]
+    fn name_redacted_copies() {
+        assert_eq!(
+            copy_path(Path::new("logs/app.log"), None),
+            PathBuf::from("logs/app.log.redacted")
+        );
+        assert_eq!(
+            copy_path(Path::new("logs/app.log.gz"), Some(Path::new("out"))),
+            PathBuf::from("out/app.log.redacted")
+        );
+    }
+}
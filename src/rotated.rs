@@ -0,0 +1,149 @@
+use crate::{error::Error, Result};
+use chrono::NaiveDate;
+use std::{
+    cmp::Reverse,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Extension of compressed segments.
+const COMPRESSED_SUFFIX: &str = ".gz";
+
+/// Age of a segment in a rotated set. Ages order segments oldest first.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Age {
+    /// Rotated with a date suffix (ex: app.log-20220101).
+    Dated(NaiveDate),
+    /// Rotated with a number suffix. Higher numbers are older (ex: app.log.2 is older than
+    /// app.log.1).
+    Numbered(Reverse<u64>),
+    /// The file being logged to.
+    Live,
+}
+
+/// Age of a file named `name` in the rotated set of a file named `base`. Return `None` if the file
+/// isn't part of the set.
+fn age(base: &str, name: &str) -> Option<Age> {
+    if name == base {
+        return Some(Age::Live);
+    }
+
+    let suffix = name.strip_prefix(base)?;
+    let suffix = suffix.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(suffix);
+    let value = suffix.strip_prefix(['.', '-'])?;
+    let digits: String = value.chars().filter(|c| *c != '-').collect();
+
+    if value.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Eight digits are read as a date (ex: app.log-20220101 or app.log-2022-01-01).
+    if digits.len() == 8 {
+        let n: u32 = digits.parse().ok()?;
+        let date = NaiveDate::from_ymd_opt((n / 10_000) as i32, n / 100 % 100, n % 100);
+
+        if let Some(date) = date {
+            return Some(Age::Dated(date));
+        }
+    }
+
+    if digits.len() != value.len() {
+        return None;
+    }
+
+    value.parse().ok().map(|n| Age::Numbered(Reverse(n)))
+}
+
+/// Name of a file as a string, if it has one.
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+/// Find the segments of the rotated set a file belongs to, ordered oldest to newest. Siblings
+/// named like the file with a number or date suffix, optionally compressed, are segments (ex:
+/// app.log-20220101, app.log.2.gz, app.log.1 then app.log). Dated segments are ordered before
+/// numbered ones.
+pub(crate) fn segments(path: &Path) -> Result<Vec<PathBuf>> {
+    let base = match file_name(path) {
+        Some(base) => base,
+        None => return Ok(vec![path.to_owned()]),
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let mut res = vec![];
+    let entries = fs::read_dir(dir).map_err(|e| Error::from(e).with_path(dir))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::from(e).with_path(dir))?;
+        let name = entry.file_name();
+
+        if let Some(age) = name.to_str().and_then(|name| age(base, name)) {
+            res.push((age, path.with_file_name(name)));
+        }
+    }
+
+    res.sort();
+
+    // A missing file is kept so it's reported when read.
+    if res.is_empty() {
+        return Ok(vec![path.to_owned()]);
+    }
+
+    Ok(res.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Check if a file is a rotated segment of `base` rather than `base` itself.
+pub fn is_segment_of(path: &Path, base: &Path) -> bool {
+    path.parent() == base.parent()
+        && matches!(
+            (file_name(base), file_name(path)),
+            (Some(base), Some(name)) if age(base, name).is_some_and(|a| a != Age::Live)
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn order_rotated_segments() {
+        let dir = TestDir::new("rotated");
+
+        for name in [
+            "app.log",
+            "app.log.1",
+            "app.log.2.gz",
+            "app.log.10.gz",
+            "app.log-20220101",
+            "app.log-2021-12-31.gz",
+            "app.log.bak",
+            "other.log.1",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+
+        let names: Vec<String> = segments(&dir.join("app.log"))
+            .unwrap()
+            .iter()
+            .map(|p| file_name(p).unwrap().to_string())
+            .collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "app.log-2021-12-31.gz",
+                "app.log-20220101",
+                "app.log.10.gz",
+                "app.log.2.gz",
+                "app.log.1",
+                "app.log"
+            ]
+        );
+        assert!(is_segment_of(&dir.join("app.log.1"), &dir.join("app.log")));
+        assert!(!is_segment_of(&dir.join("app.log"), &dir.join("app.log")));
+    }
+}
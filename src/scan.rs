@@ -0,0 +1,204 @@
+use crate::{lines::Lines, search};
+use memchr::{memchr, memchr_iter};
+use std::{borrow::Cow, ops::Range, panic, thread};
+
+/// Bytes below which a file is scanned on a single thread.
+pub(crate) const PARALLEL_THRESHOLD: u64 = 16 * 1024 * 1024;
+
+/// Bytes filtered at a time when filtering stops after a number of lines.
+const LIMITED_CHUNK_LEN: usize = 1024 * 1024;
+
+/// Number of worker threads to scan with.
+pub(crate) fn workers() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Split a buffer into up to `n` ranges. Ranges start and end on line boundaries.
+pub(crate) fn chunks(bytes: &[u8], n: usize) -> Vec<Range<usize>> {
+    let mut bounds = vec![0];
+
+    for i in 1..n {
+        let target = bytes.len() * i / n;
+        let last = *bounds.last().unwrap();
+
+        if target <= last {
+            continue;
+        }
+
+        // Move the bound past the end of the line it falls in.
+        let end = memchr(b'\n', &bytes[target - 1..]).map_or(bytes.len(), |i| target + i);
+
+        if end < bytes.len() && end > last {
+            bounds.push(end);
+        }
+    }
+
+    bounds.push(bytes.len());
+
+    bounds.windows(2).map(|w| w[0]..w[1]).collect()
+}
+
+/// Filter the lines of a buffer on `threads` workers. Lines containing the keywords of `matcher`
+/// (or every line without a matcher) are kept if they also pass `keep`. Lines are numbered across
+/// the whole buffer and returned in line order. Invalid UTF-8 is replaced.
+pub(crate) fn filter_bytes<F>(
+    bytes: &[u8],
+    threads: usize,
+    matcher: Option<&search::Matcher>,
+    keep: F,
+) -> Lines
+where
+    F: Fn(&str) -> bool + Sync,
+{
+    let chunks = chunks(bytes, threads);
+
+    let results: Vec<(usize, Lines)> = if chunks.len() == 1 {
+        vec![filter_chunk(bytes, matcher, &keep)]
+    } else {
+        thread::scope(|s| {
+            let handles: Vec<_> = chunks
+                .iter()
+                .map(|r| s.spawn(|| filter_chunk(&bytes[r.clone()], matcher, &keep)))
+                .collect();
+
+            handles
+                .into_iter()
+                .map(|h| h.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+                .collect()
+        })
+    };
+
+    // Offset each chunk's line numbers by the lines in the chunks before it.
+    let mut res = Lines::new(vec![]);
+    let mut offset = 0;
+
+    for (count, lines) in results {
+        extend(&mut res, offset, lines);
+        offset += count;
+    }
+
+    res
+}
+
+/// Filter the lines of a buffer like `filter_bytes` on one thread, stopping once `n` lines are
+/// kept. The buffer is filtered in chunks from its start, so chunks after the one holding the
+/// last line kept are never read.
+pub(crate) fn filter_bytes_until<F>(
+    bytes: &[u8],
+    matcher: Option<&search::Matcher>,
+    keep: F,
+    n: usize,
+) -> Lines
+where
+    F: Fn(&str) -> bool,
+{
+    let mut res = Lines::new(vec![]);
+    let (mut start, mut offset) = (0, 0);
+
+    while start < bytes.len() && res.enumerated_lines().len() < n {
+        let target = (start + LIMITED_CHUNK_LEN).min(bytes.len());
+        let end = memchr(b'\n', &bytes[target..]).map_or(bytes.len(), |i| target + i + 1);
+        let (count, lines) = filter_chunk(&bytes[start..end], matcher, &keep);
+
+        extend(&mut res, offset, lines);
+        offset += count;
+        start = end;
+    }
+
+    let replaced = res.replaced().to_vec();
+
+    Lines::new(res.into_enumerated_lines().into_iter().take(n).collect()).with_replaced(replaced)
+}
+
+/// Add the lines kept from a chunk to `res`, offsetting their line numbers by `offset`.
+fn extend(res: &mut Lines, offset: usize, lines: Lines) {
+    let replaced = lines.replaced().to_vec();
+
+    for (ln, line) in lines.into_enumerated_lines() {
+        res.push(offset + ln, line, replaced.binary_search(&ln).is_ok());
+    }
+}
+
+/// Filter the lines of a chunk. Return the number of lines in the chunk and the lines kept,
+/// numbered from the start of the chunk.
+fn filter_chunk<F>(bytes: &[u8], matcher: Option<&search::Matcher>, keep: &F) -> (usize, Lines)
+where
+    F: Fn(&str) -> bool,
+{
+    let candidates = match matcher {
+        Some(m) => m.find_lines(bytes),
+        None => line_ranges(bytes).into_iter().enumerate().collect(),
+    };
+
+    let mut res = Lines::new(vec![]);
+
+    for (ln, r) in candidates {
+        let line = String::from_utf8_lossy(&bytes[r]);
+
+        // Lines are only copied once they are kept, and only copied lines had bytes replaced.
+        if keep(&line) {
+            let replaced = matches!(line, Cow::Owned(_));
+            res.push(ln, line.into_owned(), replaced);
+        }
+    }
+
+    (count_lines(bytes), res)
+}
+
+/// Byte ranges of every line in a buffer, without line endings.
+fn line_ranges(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut res = vec![];
+    let mut start = 0;
+
+    for end in memchr_iter(b'\n', bytes) {
+        res.push(search::trim_line_ending(start..end, bytes));
+        start = end + 1;
+    }
+
+    if start < bytes.len() {
+        res.push(search::trim_line_ending(start..bytes.len(), bytes));
+    }
+
+    res
+}
+
+/// Count the lines in a buffer. A final line without a line ending is still a line.
+fn count_lines(bytes: &[u8]) -> usize {
+    let count = memchr_iter(b'\n', bytes).count();
+
+    match bytes.last() {
+        Some(b) if *b != b'\n' => count + 1,
+        _ => count,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_bytes_in_chunks() {
+        let lines: Vec<String> = (0..1000).map(|i| format!("line {}", i)).collect();
+        let bytes = lines.join("\n").into_bytes();
+
+        let chunks = chunks(&bytes, 7);
+        assert_eq!(chunks.len(), 7);
+        assert_eq!(chunks.last().unwrap().end, bytes.len());
+
+        let res = filter_bytes(&bytes, 7, None, |l| l.ends_with('7')).into_enumerated_lines();
+        assert_eq!(res.len(), 100);
+        assert_eq!(res[0], (7, "line 7".to_string()));
+        assert_eq!(res[99], (997, "line 997".to_string()));
+
+        let matcher = search::Matcher::new(&["line 99".to_string()], true);
+        let res = filter_bytes(&bytes, 7, Some(&matcher), |_| true).into_enumerated_lines();
+        assert_eq!(res.len(), 11);
+        assert_eq!(res[1], (990, "line 990".to_string()));
+
+        let res = filter_bytes_until(&bytes, Some(&matcher), |_| true, 2).into_enumerated_lines();
+        assert_eq!(
+            res,
+            vec![(99, "line 99".to_string()), (990, "line 990".to_string())]
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
+use memchr::{memchr, memchr_iter, memrchr};
+use std::ops::Range;
+
+/// Keywords compiled into a single multi-pattern automaton. The automaton searches raw bytes
+/// using SIMD prefilters, and line boundaries are only found around candidate hits.
+pub(crate) struct Matcher {
+    automaton: AhoCorasick,
+    keywords: usize,
+    all: bool,
+}
+
+impl Matcher {
+    /// Compile keywords into a `Matcher`. If `all` is true lines must contain every keyword,
+    /// otherwise any keyword.
+    pub(crate) fn new(keywords: &[String], all: bool) -> Matcher {
+        Matcher {
+            automaton: AhoCorasickBuilder::new()
+                .auto_configure(keywords)
+                .build(keywords),
+            keywords: keywords.len(),
+            all,
+        }
+    }
+
+    /// Check if a line contains the keywords.
+    fn matches(&self, line: &[u8]) -> bool {
+        if !self.all || self.keywords < 2 {
+            return self.automaton.is_match(line);
+        }
+
+        // Overlapping matches are needed so keywords sharing bytes are each found.
+        let mut found = vec![false; self.keywords];
+        let mut remaining = self.keywords;
+
+        for m in self.automaton.find_overlapping_iter(line) {
+            if !found[m.pattern()] {
+                found[m.pattern()] = true;
+                remaining -= 1;
+
+                if remaining == 0 {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    /// Find the lines of a buffer containing the keywords. Return each line's number, counted
+    /// from the start of the buffer, and its byte range without the line ending.
+    pub(crate) fn find_lines(&self, bytes: &[u8]) -> Vec<(usize, Range<usize>)> {
+        let mut res = vec![];
+        let mut ln = 0;
+        let mut counted = 0;
+        let mut pos = 0;
+
+        while pos < bytes.len() {
+            let hit = match self.automaton.find(&bytes[pos..]) {
+                Some(m) => pos + m.start(),
+                None => break,
+            };
+            let start = memrchr(b'\n', &bytes[pos..hit]).map_or(pos, |i| pos + i + 1);
+            let end = memchr(b'\n', &bytes[hit..]).map_or(bytes.len(), |i| hit + i);
+
+            // Count the lines skipped since the last hit.
+            ln += memchr_iter(b'\n', &bytes[counted..start]).count();
+            counted = start;
+
+            let line = trim_line_ending(start..end, bytes);
+
+            if self.matches(&bytes[line.clone()]) {
+                res.push((ln, line));
+            }
+
+            pos = end + 1;
+        }
+
+        res
+    }
+}
+
+/// Exclude a trailing carriage return from a line's byte range.
+pub(crate) fn trim_line_ending(line: Range<usize>, bytes: &[u8]) -> Range<usize> {
+    if line.end > line.start && bytes[line.end - 1] == b'\r' {
+        line.start..line.end - 1
+    } else {
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keywords(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn find_lines_with_any_keyword() {
+        let bytes = b"first line\r\nsecond error\nthird\nfourth warning\nerror warning";
+        let matcher = Matcher::new(&keywords(&["error", "warning"]), false);
+        let res: Vec<(usize, &[u8])> = matcher
+            .find_lines(bytes)
+            .into_iter()
+            .map(|(ln, r)| (ln, &bytes[r]))
+            .collect();
+
+        assert_eq!(
+            res,
+            vec![
+                (1, &b"second error"[..]),
+                (3, &b"fourth warning"[..]),
+                (4, &b"error warning"[..])
+            ]
+        );
+    }
+
+    #[test]
+    fn find_lines_with_all_keywords() {
+        let bytes = b"abc\nab bc\nbc\nabc ab";
+        let matcher = Matcher::new(&keywords(&["ab", "bc"]), true);
+        let lns: Vec<usize> = matcher.find_lines(bytes).into_iter().map(|r| r.0).collect();
+
+        assert_eq!(lns, vec![0, 1, 3]);
+    }
+}
@@ -0,0 +1,208 @@
+#[cfg(feature = "color")]
+use crate::color::Palette;
+use crate::{query::Match, Result};
+use std::{io::Write, path::Path};
+
+/// Where a query writes the lines it kept.
+pub trait Sink {
+    /// Write the lines kept from the source at position `i` of `total` sources.
+    fn write(&mut self, i: usize, total: usize, name: &Path, matches: &[Match]) -> Result<()>;
+}
+
+/// Collects every line kept.
+impl Sink for Vec<Match> {
+    fn write(&mut self, _: usize, _: usize, _: &Path, matches: &[Match]) -> Result<()> {
+        self.extend_from_slice(matches);
+
+        Ok(())
+    }
+}
+
+/// Writes lines as text under a header for each source, numbered and labelled with their
+/// segment. This is how `log-cli` displays results.
+pub struct TextSink<W> {
+    writer: W,
+    #[cfg(feature = "color")]
+    palette: Option<Palette>,
+}
+
+impl<W: Write> TextSink<W> {
+    /// Create a `TextSink` writing to `writer` (ex: stdout or a file).
+    pub fn new(writer: W) -> TextSink<W> {
+        TextSink {
+            writer,
+            #[cfg(feature = "color")]
+            palette: None,
+        }
+    }
+
+    /// Set the colours lines are written in and return a new `TextSink`.
+    #[cfg(feature = "color")]
+    pub fn with_palette(self, palette: Palette) -> TextSink<W> {
+        let mut sink = self;
+
+        sink.palette = Some(palette);
+
+        sink
+    }
+
+    /// Take the writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Sink for TextSink<W> {
+    fn write(&mut self, i: usize, total: usize, name: &Path, matches: &[Match]) -> Result<()> {
+        // To pad line numbers, get the length of the number of lines as a string.
+        let pad_len = matches.len().to_string().len();
+
+        write!(self.writer, "{}", header(i, total, name))?;
+
+        for m in matches {
+            let line = match m.segment() {
+                Some(label) => format!("ln{:0pad_len$} [{}] {}", m.ln(), label, m.line()),
+                None => format!("ln{:0pad_len$} {}", m.ln(), m.line()),
+            };
+
+            #[cfg(feature = "color")]
+            if let Some(palette) = &self.palette {
+                write!(self.writer, "\n{}", palette.paint(&line))?;
+                continue;
+            }
+
+            write!(self.writer, "\n{}", line)?;
+        }
+
+        writeln!(self.writer)?;
+
+        Ok(())
+    }
+}
+
+/// Writes lines as JSON objects, one per line, with the source they were read from.
+pub struct JsonSink<W> {
+    writer: W,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Create a `JsonSink` writing to `writer`.
+    pub fn new(writer: W) -> JsonSink<W> {
+        JsonSink { writer }
+    }
+
+    /// Take the writer back.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> Sink for JsonSink<W> {
+    fn write(&mut self, _: usize, _: usize, name: &Path, matches: &[Match]) -> Result<()> {
+        let source = json_string(&name.to_string_lossy());
+
+        for m in matches {
+            let segment = m.segment().map_or("null".to_string(), json_string);
+
+            writeln!(
+                self.writer,
+                r#"{{"source":{},"ln":{},"line":{},"segment":{},"replaced":{}}}"#,
+                source,
+                m.ln(),
+                json_string(m.line()),
+                segment,
+                m.is_replaced()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Display the header for the source at position `i` of `total` sources.
+pub(crate) fn header(i: usize, total: usize, name: &Path) -> String {
+    format!("\nFile ({}/{}): {}", i + 1, total, name.display())
+}
+
+/// Quote and escape a string as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+
+    res.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c),
+        }
+    }
+
+    res.push('"');
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        query::{Instructions, Query},
+        source::MemorySource,
+    };
+    use std::path::PathBuf;
+
+    fn query(text: &str) -> Query {
+        let source = MemorySource::new(PathBuf::from("app.log"), text.as_bytes().to_vec());
+
+        Query::build(
+            Instructions::new()
+                .add_source(source)
+                .add_keyword("target".to_string()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn write_text() {
+        let mut sink = TextSink::new(vec![]);
+
+        query("first\ntarget one\nlast target\n")
+            .execute(&mut sink)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "\nFile (1/1): app.log\nln1 target one\nln2 last target\n"
+        );
+    }
+
+    #[test]
+    fn write_json() {
+        let mut sink = JsonSink::new(vec![]);
+
+        query("first\ntarget \"quoted\"\ttab\n")
+            .execute(&mut sink)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(sink.into_inner()).unwrap(),
+            "{\"source\":\"app.log\",\"ln\":1,\"line\":\"target \\\"quoted\\\"\\ttab\",\
+             \"segment\":null,\"replaced\":false}\n"
+        );
+    }
+
+    #[test]
+    fn collect_matches() {
+        let mut matches = vec![];
+
+        query("target\nother\n").execute(&mut matches).unwrap();
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].line(), "target");
+    }
+}
@@ -0,0 +1,389 @@
+use crate::{fields, lines::EnumeratedLines, timestamp::Parser, Result};
+use chrono::NaiveDateTime;
+use std::{
+    cmp::Ordering,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    mem,
+    path::PathBuf,
+    process,
+    sync::atomic::{self, AtomicUsize},
+    vec,
+};
+
+/// Bytes of line text sorted in memory before sorted runs are spilled to temporary files.
+const MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Runs spilled by this process, used to name their files.
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+/// Keys lines can be sorted by.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SortBy {
+    /// The leading timestamp of a line.
+    Timestamp,
+    /// The severity level of a line.
+    Level,
+    /// The value of a `key=value` field.
+    Field(String),
+}
+
+impl SortBy {
+    /// Parse a sort key. Names other than timestamp and level are field names.
+    pub fn parse(name: &str) -> Option<SortBy> {
+        match name {
+            "timestamp" => Some(SortBy::Timestamp),
+            "level" => Some(SortBy::Level),
+            name if fields::is_field_name(name) => Some(SortBy::Field(name.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// Value of the sort key of a line. Numeric field values sort before text.
+#[derive(Debug, PartialEq, PartialOrd)]
+enum SortValue {
+    Time(NaiveDateTime),
+    Level(fields::Level),
+    Number(f64),
+    Text(String),
+}
+
+/// A line with its sort key and line number.
+type Keyed = (Option<SortValue>, usize, String);
+
+/// Order to sort lines in.
+#[derive(Clone, Debug)]
+pub struct LineOrder {
+    by: SortBy,
+    reverse: bool,
+    timestamps: Parser,
+    budget: usize,
+}
+
+impl LineOrder {
+    /// Create a `LineOrder` sorting by `by`, in descending order if `reverse` is true.
+    pub fn new(by: SortBy, reverse: bool) -> LineOrder {
+        LineOrder {
+            by,
+            reverse,
+            timestamps: Parser::default(),
+            budget: MEMORY_BUDGET,
+        }
+    }
+
+    /// Set how timestamps are read and return a new `LineOrder`.
+    pub(crate) fn with_timestamps(self, timestamps: Parser) -> LineOrder {
+        let mut order = self;
+
+        order.timestamps = timestamps;
+
+        order
+    }
+
+    /// Set the bytes of line text sorted in memory and return a new `LineOrder`.
+    #[cfg(test)]
+    fn with_budget(self, budget: usize) -> LineOrder {
+        let mut order = self;
+
+        order.budget = budget;
+
+        order
+    }
+
+    /// Sort key of a line.
+    fn key(&self, line: &str) -> Option<SortValue> {
+        match &self.by {
+            SortBy::Timestamp => self.timestamps.instant(line).map(SortValue::Time),
+            SortBy::Level => fields::level(line).map(SortValue::Level),
+            SortBy::Field(name) => {
+                fields::field(line, name).map(|value| match value.parse::<f64>() {
+                    Ok(n) if n.is_finite() => SortValue::Number(n),
+                    _ => SortValue::Text(value.to_string()),
+                })
+            }
+        }
+    }
+
+    /// Compare sort keys. Lines without a key sort last in either direction.
+    fn compare(&self, a: &Option<SortValue>, b: &Option<SortValue>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => {
+                let ord = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+
+                if self.reverse {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    /// Sort lines held in memory, keeping lines with equal keys in their order. Lines keep their
+    /// line numbers.
+    pub(crate) fn sort(&self, lines: EnumeratedLines) -> EnumeratedLines {
+        let run = lines
+            .into_iter()
+            .map(|(ln, line)| (self.key(&line), ln, line))
+            .collect();
+
+        self.sort_run(run)
+            .into_iter()
+            .map(|(_, ln, line)| (ln, line))
+            .collect()
+    }
+
+    /// Create a `Sorter` lines can be pushed to one at a time.
+    pub(crate) fn sorter(&self) -> Sorter<'_> {
+        Sorter {
+            order: self,
+            run: vec![],
+            bytes: 0,
+            runs: vec![],
+        }
+    }
+
+    // Sort a run of keyed lines in memory.
+    fn sort_run(&self, run: Vec<Keyed>) -> Vec<Keyed> {
+        let mut run = run;
+
+        run.sort_by(|a, b| self.compare(&a.0, &b.0));
+
+        run
+    }
+}
+
+impl fmt::Display for LineOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.by {
+            SortBy::Timestamp => write!(f, "by timestamp")?,
+            SortBy::Level => write!(f, "by level")?,
+            SortBy::Field(name) => write!(f, "by field {}", name)?,
+        }
+
+        match self.reverse {
+            true => write!(f, ", reversed"),
+            false => Ok(()),
+        }
+    }
+}
+
+/// Sorts lines pushed one at a time, so lines can be sorted while they're read. Once the lines
+/// held exceed the memory budget, they're sorted and spilled to a temporary file as a run, and
+/// runs are merged once every line is pushed.
+pub(crate) struct Sorter<'a> {
+    order: &'a LineOrder,
+    run: Vec<Keyed>,
+    bytes: usize,
+    runs: Vec<Run>,
+}
+
+impl<'a> Sorter<'a> {
+    /// Add line `ln` to the lines sorted.
+    pub(crate) fn push(&mut self, ln: usize, line: String) -> Result<()> {
+        self.bytes += line.len();
+        self.run.push((self.order.key(&line), ln, line));
+
+        if self.bytes >= self.order.budget {
+            let run = mem::take(&mut self.run);
+
+            self.runs.push(Run::spill(self.order.sort_run(run))?);
+            self.bytes = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Finish pushing lines and iterate over them in order. Lines still held in memory are
+    /// merged with the spilled runs without being spilled.
+    pub(crate) fn finish(self) -> Result<Sorted<'a>> {
+        let mut sources = self
+            .runs
+            .into_iter()
+            .map(RunReader::open)
+            .collect::<Result<Vec<_>>>()?;
+
+        // Lines held in memory were pushed last, so they're merged after the spilled runs.
+        sources.push(RunReader::Memory(self.order.sort_run(self.run).into_iter()));
+
+        let heads = sources
+            .iter_mut()
+            .map(|s| s.next_line(self.order))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Sorted {
+            order: self.order,
+            sources,
+            heads,
+        })
+    }
+}
+
+/// Lines merged in order from the runs of a `Sorter`. Only the next line of each run is held in
+/// memory.
+pub(crate) struct Sorted<'a> {
+    order: &'a LineOrder,
+    sources: Vec<RunReader>,
+    heads: Vec<Option<Keyed>>,
+}
+
+impl Iterator for Sorted<'_> {
+    type Item = Result<(usize, String)>;
+
+    fn next(&mut self) -> Option<Result<(usize, String)>> {
+        let mut min: Option<usize> = None;
+
+        // Lines with equal keys are taken from earlier runs first, so lines keep their order.
+        for (i, head) in self.heads.iter().enumerate() {
+            let Some((key, _, _)) = head else { continue };
+
+            match min.and_then(|m| self.heads[m].as_ref()) {
+                Some((m, _, _)) if self.order.compare(key, m) != Ordering::Less => {}
+                _ => min = Some(i),
+            }
+        }
+
+        let i = min?;
+        let next = match self.sources[i].next_line(self.order) {
+            Ok(next) => next,
+            Err(e) => return Some(Err(e)),
+        };
+
+        mem::replace(&mut self.heads[i], next).map(|(_, ln, line)| Ok((ln, line)))
+    }
+}
+
+/// A sorted run of lines spilled to a temporary file. The file is removed once the run is
+/// dropped.
+struct Run {
+    path: PathBuf,
+}
+
+impl Run {
+    /// Write a sorted run of lines to a temporary file.
+    fn spill(run: Vec<Keyed>) -> Result<Run> {
+        let id = RUNS.fetch_add(1, atomic::Ordering::SeqCst);
+        let path = std::env::temp_dir().join(format!("log-cli-sort-{}-{}", process::id(), id));
+        let res = Run { path };
+        let mut writer = BufWriter::new(File::create(&res.path)?);
+
+        for (_, ln, line) in run {
+            writeln!(writer, "{}\t{}", ln, line)?;
+        }
+        writer.flush()?;
+
+        Ok(res)
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Reads the lines of a sorted run in order.
+enum RunReader {
+    /// A run spilled to a temporary file. The run is held so its file is removed once the reader
+    /// is dropped.
+    File { reader: BufReader<File>, _run: Run },
+    /// A run held in memory.
+    Memory(vec::IntoIter<Keyed>),
+}
+
+impl RunReader {
+    /// Open a spilled run for reading.
+    fn open(run: Run) -> Result<RunReader> {
+        Ok(RunReader::File {
+            reader: BufReader::new(File::open(&run.path)?),
+            _run: run,
+        })
+    }
+
+    /// Read the next line of the run, keyed by `order`.
+    fn next_line(&mut self, order: &LineOrder) -> Result<Option<Keyed>> {
+        let reader = match self {
+            RunReader::File { reader, .. } => reader,
+            RunReader::Memory(lines) => return Ok(lines.next()),
+        };
+
+        // Lines can end with a carriage return, so only the line feed written is removed.
+        let mut record = String::new();
+
+        if reader.read_line(&mut record)? == 0 {
+            return Ok(None);
+        }
+        if record.ends_with('\n') {
+            record.pop();
+        }
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid sort run");
+        let (ln, line) = record.split_once('\t').ok_or_else(invalid)?;
+        let ln = ln.parse().map_err(|_| invalid())?;
+
+        Ok(Some((order.key(line), ln, line.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> EnumeratedLines {
+        lines.iter().map(|l| l.to_string()).enumerate().collect()
+    }
+
+    fn numbers(lines: EnumeratedLines) -> Vec<usize> {
+        lines.iter().map(|(ln, _)| *ln).collect()
+    }
+
+    #[test]
+    fn sort_lines_by_key() {
+        let input = lines(&[
+            "2022-01-01 07:00:02,0 [info] worker=2 took=10",
+            "2022-01-01 07:00:00,0 [error] worker=1 took=9.5",
+            "no timestamp",
+            "2022-01-01 07:00:01,0 [debug] worker=1 took=100",
+            "2022-01-01 07:00:00,0 [warning] worker=3",
+        ]);
+
+        let order = LineOrder::new(SortBy::Timestamp, false);
+        assert_eq!(numbers(order.sort(input.clone())), vec![1, 4, 3, 0, 2]);
+
+        let order = LineOrder::new(SortBy::Level, true);
+        assert_eq!(numbers(order.sort(input.clone())), vec![1, 4, 0, 3, 2]);
+
+        let order = LineOrder::new(SortBy::parse("took").unwrap(), false);
+        assert_eq!(numbers(order.sort(input)), vec![1, 0, 3, 2, 4]);
+    }
+
+    #[test]
+    fn merge_spilled_runs() {
+        let input: EnumeratedLines = (0..100)
+            .map(|i| {
+                (
+                    i,
+                    format!("2022-01-01 07:00:{:02},0 line {}", (i * 7) % 60, i),
+                )
+            })
+            .collect();
+
+        let order = LineOrder::new(SortBy::Timestamp, false);
+        let expected = order.sort(input.clone());
+
+        let order = order.with_budget(200);
+        let mut sorter = order.sorter();
+        for (ln, line) in input {
+            sorter.push(ln, line).unwrap();
+        }
+        assert!(sorter.runs.len() > 1);
+
+        let merged: EnumeratedLines = sorter.finish().unwrap().map(Result::unwrap).collect();
+        assert_eq!(merged, expected);
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{buffer, error::Error, Result};
+use flate2::read::MultiGzDecoder;
+use std::{
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// Size of the header and data blocks of tar archives.
+const TAR_BLOCK: usize = 512;
+
+/// Name given to standard input.
+const STDIN_NAME: &str = "<stdin>";
+
+/// Where a query reads lines from. Sources are read as raw bytes, which are split into lines and
+/// decoded as instructed.
+pub trait LineSource: Send + Sync {
+    /// Access the name of the source, shown in headers and attached to errors.
+    fn name(&self) -> &Path;
+
+    /// Open a reader over the bytes of the source.
+    fn open(&self) -> Result<Box<dyn Read + '_>>;
+
+    /// Access the path of the file the source reads as it is, if it reads one. Files are read
+    /// directly, so they can be searched, seeked in, indexed and read with rotated segments.
+    fn file(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// A log file. Gzip files are decompressed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileSource {
+    path: PathBuf,
+}
+
+impl FileSource {
+    /// Create a `FileSource` reading `path`.
+    pub fn new(path: PathBuf) -> FileSource {
+        FileSource { path }
+    }
+}
+
+impl LineSource for FileSource {
+    fn name(&self) -> &Path {
+        &self.path
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        let reader = buffer::read_file(&self.path)?;
+
+        if buffer::is_compressed(&self.path) {
+            return Ok(Box::new(MultiGzDecoder::new(reader)));
+        }
+
+        Ok(Box::new(reader))
+    }
+
+    fn file(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+}
+
+/// Standard input. It's read in full the first time it's opened and kept, so it can be queried
+/// again.
+#[derive(Debug, Default)]
+pub struct StdinSource {
+    bytes: OnceLock<Vec<u8>>,
+}
+
+impl StdinSource {
+    /// Create a `StdinSource`.
+    pub fn new() -> StdinSource {
+        StdinSource::default()
+    }
+}
+
+impl LineSource for StdinSource {
+    fn name(&self) -> &Path {
+        Path::new(STDIN_NAME)
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        if self.bytes.get().is_none() {
+            let mut bytes = vec![];
+
+            io::stdin().lock().read_to_end(&mut bytes)?;
+            self.bytes.get_or_init(|| bytes);
+        }
+
+        Ok(Box::new(self.bytes.get().map_or(&[][..], Vec::as_slice)))
+    }
+}
+
+/// A gzip stream read from another source (ex: gzip piped to standard input).
+pub struct GzipSource<S> {
+    source: S,
+}
+
+impl<S: LineSource> GzipSource<S> {
+    /// Create a `GzipSource` decompressing the bytes of `source`.
+    pub fn new(source: S) -> GzipSource<S> {
+        GzipSource { source }
+    }
+}
+
+impl<S: LineSource> LineSource for GzipSource<S> {
+    fn name(&self) -> &Path {
+        self.source.name()
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(MultiGzDecoder::new(self.source.open()?)))
+    }
+}
+
+/// A file stored in a tar archive read from another source. Compressed archives are read by
+/// wrapping their source in a `GzipSource`. The source is named `archive:member`.
+pub struct TarSource<S> {
+    archive: S,
+    member: PathBuf,
+    name: PathBuf,
+}
+
+impl<S: LineSource> TarSource<S> {
+    /// Create a `TarSource` reading `member` from the tar archive read from `archive`.
+    pub fn new(archive: S, member: PathBuf) -> TarSource<S> {
+        let name = PathBuf::from(format!("{}:{}", archive.name().display(), member.display()));
+
+        TarSource {
+            archive,
+            member,
+            name,
+        }
+    }
+}
+
+impl<S: LineSource> LineSource for TarSource<S> {
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        let mut reader = self.archive.open()?;
+        let mut header = [0; TAR_BLOCK];
+
+        // Entries are a header block followed by their data padded to whole blocks. Archives end
+        // with empty blocks.
+        loop {
+            if !read_block(&mut reader, &mut header)? || header.iter().all(|b| *b == 0) {
+                return Err(Error::Format(format!(
+                    "{} was not found in {}",
+                    self.member.display(),
+                    self.archive.name().display()
+                )));
+            }
+
+            let size = tar_size(&header).ok_or_else(|| {
+                Error::Format(format!(
+                    "{}: invalid tar header",
+                    self.archive.name().display()
+                ))
+            })?;
+
+            // Only regular files are read. Other entries (ex: directories) are skipped.
+            if tar_path(&header) == self.member && matches!(header[156], b'0' | 0) {
+                return Ok(Box::new(reader.take(size)));
+            }
+
+            let padded = size.div_ceil(TAR_BLOCK as u64) * TAR_BLOCK as u64;
+            io::copy(&mut (&mut reader).take(padded), &mut io::sink())?;
+        }
+    }
+}
+
+/// Lines held in memory, named like a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemorySource {
+    name: PathBuf,
+    bytes: Vec<u8>,
+}
+
+impl MemorySource {
+    /// Create a `MemorySource` of `bytes` named `name`.
+    pub fn new(name: PathBuf, bytes: Vec<u8>) -> MemorySource {
+        MemorySource { name, bytes }
+    }
+}
+
+impl LineSource for MemorySource {
+    fn name(&self) -> &Path {
+        &self.name
+    }
+
+    fn open(&self) -> Result<Box<dyn Read + '_>> {
+        Ok(Box::new(self.bytes.as_slice()))
+    }
+}
+
+/// Read a whole block into `buf`. Return `false` if the reader ended first.
+fn read_block<R: Read>(reader: &mut R, buf: &mut [u8; TAR_BLOCK]) -> Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(Error::from(e)),
+    }
+}
+
+/// Read the size of a tar entry from its header, stored as octal digits.
+fn tar_size(header: &[u8; TAR_BLOCK]) -> Option<u64> {
+    let digits = String::from_utf8_lossy(&header[124..136]);
+
+    u64::from_str_radix(digits.trim_matches(|c: char| c == '\0' || c == ' '), 8).ok()
+}
+
+/// Read the path of a tar entry from its header. Long paths of ustar archives are split between
+/// a prefix and a name.
+fn tar_path(header: &[u8; TAR_BLOCK]) -> PathBuf {
+    let field = |bytes: &[u8]| {
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+    let name = field(&header[..100]);
+
+    if &header[257..262] == b"ustar" && header[345] != 0 {
+        Path::new(&field(&header[345..500])).join(name)
+    } else {
+        PathBuf::from(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn read(source: &dyn LineSource) -> String {
+        let mut text = String::new();
+
+        source.open().unwrap().read_to_string(&mut text).unwrap();
+
+        text
+    }
+
+    fn tar_entry(path: &str, text: &str) -> Vec<u8> {
+        let mut header = vec![0; TAR_BLOCK];
+
+        header[..path.len()].copy_from_slice(path.as_bytes());
+        header[124..135].copy_from_slice(format!("{:011o}", text.len()).as_bytes());
+        header[156] = b'0';
+        header[257..262].copy_from_slice(b"ustar");
+
+        let mut data = text.as_bytes().to_vec();
+        data.resize(text.len().div_ceil(TAR_BLOCK) * TAR_BLOCK, 0);
+
+        [header, data].concat()
+    }
+
+    #[test]
+    fn read_sources() {
+        let memory = MemorySource::new(PathBuf::from("app.log"), b"first\nsecond\n".to_vec());
+        assert_eq!(memory.name(), Path::new("app.log"));
+        assert_eq!(read(&memory), "first\nsecond\n");
+        assert!(memory.file().is_none());
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(b"compressed\n").unwrap();
+        let gzip = GzipSource::new(MemorySource::new(
+            PathBuf::from("app.log.gz"),
+            encoder.finish().unwrap(),
+        ));
+        assert_eq!(read(&gzip), "compressed\n");
+
+        let dir = TestDir::new("source");
+        let path = dir.join("app.log");
+        std::fs::write(&path, "on disk\n").unwrap();
+        let file = FileSource::new(path.clone());
+        assert_eq!(read(&file), "on disk\n");
+        assert_eq!(file.file(), Some(path.as_path()));
+    }
+
+    #[test]
+    fn read_tar_members() {
+        let archive = [
+            tar_entry("logs/api.log", &"a".repeat(600)),
+            tar_entry("logs/db.log", "query\n"),
+            vec![0; TAR_BLOCK * 2],
+        ]
+        .concat();
+        let source = || MemorySource::new(PathBuf::from("logs.tar"), archive.clone());
+
+        let db = TarSource::new(source(), PathBuf::from("logs/db.log"));
+        assert_eq!(db.name(), Path::new("logs.tar:logs/db.log"));
+        assert_eq!(read(&db), "query\n");
+
+        let api = TarSource::new(source(), PathBuf::from("logs/api.log"));
+        assert_eq!(read(&api).len(), 600);
+
+        let missing = TarSource::new(source(), PathBuf::from("logs/web.log"));
+        assert!(matches!(missing.open(), Err(Error::Format(_))));
+    }
+}
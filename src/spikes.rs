@@ -0,0 +1,242 @@
+use crate::{
+    lines::EnumeratedLines,
+    timestamp::{self, Parser},
+};
+use chrono::{Duration, NaiveDateTime};
+use std::{collections::BTreeMap, collections::VecDeque, fmt};
+
+/// Lowest standard deviation used for baselines, so a bucket isn't flagged for a change of a
+/// line or two after a quiet stretch.
+const MIN_DEVIATION: f64 = 1.0;
+
+/// How far a bucket must deviate from its baseline to be flagged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// Standard deviations above the baseline mean.
+    ZScore(f64),
+    /// Multiple of the baseline mean.
+    Factor(f64),
+}
+
+/// Settings for finding spikes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpikeRule {
+    bucket: Duration,
+    window: usize,
+    threshold: Threshold,
+}
+
+impl SpikeRule {
+    /// Create a `SpikeRule` counting lines in buckets of `bucket`, compared to a baseline of the
+    /// `window` buckets before them.
+    pub fn new(bucket: Duration, window: usize, threshold: Threshold) -> SpikeRule {
+        SpikeRule {
+            bucket,
+            window: window.max(1),
+            threshold,
+        }
+    }
+}
+
+/// A bucket of lines flagged as a spike.
+pub(crate) struct Spike {
+    /// Start of the bucket in UTC.
+    start: NaiveDateTime,
+    count: usize,
+    /// Mean and standard deviation of the baseline buckets.
+    mean: f64,
+    deviation: f64,
+    example: (usize, String),
+}
+
+/// Buckets whose line counts deviate from a rolling baseline of the buckets before them. Buckets
+/// without lines count towards baselines.
+pub struct Spikes {
+    rule: SpikeRule,
+    timestamps: Parser,
+    spikes: Vec<Spike>,
+    buckets: usize,
+}
+
+impl Spikes {
+    /// Find spikes in the timestamped lines of enumerated lines.
+    pub fn find(lines: &EnumeratedLines, timestamps: &Parser, rule: SpikeRule) -> Spikes {
+        let bucket_ms = rule.bucket.num_milliseconds().max(1);
+        let mut buckets: BTreeMap<i64, (usize, (usize, &str))> = BTreeMap::new();
+
+        for (ln, line) in lines.iter() {
+            if let Some(t) = timestamps.instant(line) {
+                let key = t.timestamp_millis().div_euclid(bucket_ms);
+                buckets.entry(key).or_insert((0, (*ln, line))).0 += 1;
+            }
+        }
+
+        let mut spikes = vec![];
+        let mut window: VecDeque<usize> = VecDeque::with_capacity(rule.window);
+        let mut previous: Option<i64> = None;
+
+        for (key, (count, example)) in buckets.iter() {
+            // Buckets skipped had no lines. Only the last `window` of them are in the baseline.
+            if let Some(previous) = previous {
+                let empty = (key - previous - 1).min(rule.window as i64);
+
+                for _ in 0..empty {
+                    push(&mut window, 0, rule.window);
+                }
+            }
+
+            if window.len() == rule.window {
+                let (mean, deviation) = baseline(&window);
+
+                if is_spike(*count, mean, deviation, rule.threshold) {
+                    spikes.push(Spike {
+                        start: NaiveDateTime::from_timestamp(0, 0)
+                            + Duration::milliseconds(key * bucket_ms),
+                        count: *count,
+                        mean,
+                        deviation,
+                        example: (example.0, example.1.to_string()),
+                    });
+                }
+            }
+
+            push(&mut window, *count, rule.window);
+            previous = Some(*key);
+        }
+
+        let span = match (buckets.keys().next(), buckets.keys().last()) {
+            (Some(first), Some(last)) => (last - first + 1) as usize,
+            _ => 0,
+        };
+
+        Spikes {
+            rule,
+            timestamps: timestamps.clone(),
+            spikes,
+            buckets: span,
+        }
+    }
+}
+
+/// Add a bucket count to a baseline window, dropping the oldest count once full.
+fn push(window: &mut VecDeque<usize>, count: usize, len: usize) {
+    if window.len() == len {
+        window.pop_front();
+    }
+
+    window.push_back(count);
+}
+
+/// Mean and standard deviation of the counts of a baseline window.
+fn baseline(window: &VecDeque<usize>) -> (f64, f64) {
+    let n = window.len() as f64;
+    let mean = window.iter().sum::<usize>() as f64 / n;
+    let variance = window
+        .iter()
+        .map(|c| (*c as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n;
+
+    (mean, variance.sqrt())
+}
+
+/// Check if a bucket count is past the threshold of its baseline.
+fn is_spike(count: usize, mean: f64, deviation: f64, threshold: Threshold) -> bool {
+    let count = count as f64;
+
+    match threshold {
+        Threshold::ZScore(z) => (count - mean) / deviation.max(MIN_DEVIATION) > z,
+        Threshold::Factor(factor) => count >= factor * mean.max(1.0),
+    }
+}
+
+impl fmt::Display for Spikes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Pad counts and line numbers to the widest found.
+        let count_len = self
+            .spikes
+            .iter()
+            .map(|s| s.count.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let pad_len = self
+            .spikes
+            .iter()
+            .map(|s| s.example.0.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        write!(f, "")?;
+
+        for s in self.spikes.iter() {
+            let start = self.timestamps.zone().localize(s.start);
+            let z = (s.count as f64 - s.mean) / s.deviation.max(MIN_DEVIATION);
+
+            write!(
+                f,
+                "\n{} {:>count_len$} lines (baseline {:.1}, z {:.1})",
+                start.format("%Y-%m-%d %H:%M:%S"),
+                s.count,
+                s.mean,
+                z,
+                count_len = count_len
+            )?;
+            write!(
+                f,
+                "\n    e.g. ln{:0pad_len$} {}",
+                s.example.0,
+                s.example.1,
+                pad_len = pad_len
+            )?;
+        }
+
+        write!(
+            f,
+            "\nFound {} {} in {} buckets of {}, compared to the {} buckets before each.",
+            self.spikes.len(),
+            if self.spikes.len() == 1 {
+                "spike"
+            } else {
+                "spikes"
+            },
+            self.buckets,
+            timestamp::describe_duration(self.rule.bucket),
+            self.rule.window
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(counts: &[usize]) -> EnumeratedLines {
+        counts
+            .iter()
+            .enumerate()
+            .flat_map(|(minute, n)| {
+                (0..*n)
+                    .map(move |i| format!("2022-01-01 07:{:02}:{:02},0 [error] failed", minute, i))
+            })
+            .enumerate()
+            .collect()
+    }
+
+    #[test]
+    fn find_spikes() {
+        let rule = SpikeRule::new(Duration::minutes(1), 5, Threshold::ZScore(3.0));
+        let counts = [2, 3, 2, 2, 3, 2, 20, 3, 0, 0, 2];
+        let spikes = Spikes::find(&lines(&counts), &Parser::default(), rule);
+
+        assert_eq!(spikes.spikes.len(), 1);
+        assert_eq!(spikes.spikes[0].count, 20);
+        assert_eq!(spikes.spikes[0].start.to_string(), "2022-01-01 07:06:00");
+        assert_eq!(spikes.spikes[0].example.0, 14);
+        assert_eq!(spikes.buckets, 11);
+
+        let rule = SpikeRule::new(Duration::minutes(1), 3, Threshold::Factor(2.0));
+        let spikes = Spikes::find(&lines(&counts), &Parser::default(), rule);
+        assert_eq!(spikes.spikes.len(), 2);
+        assert_eq!(spikes.spikes[1].count, 2);
+    }
+}
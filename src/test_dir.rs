@@ -0,0 +1,41 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of directories created by this process, so each test gets its own.
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+
+/// Temporary directory for the files of a test. The directory is unique to the test and removed
+/// with everything in it when dropped.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Create an empty `TestDir` named after a test.
+    pub(crate) fn new(name: &str) -> TestDir {
+        let n = CREATED.fetch_add(1, Ordering::Relaxed);
+        let path =
+            std::env::temp_dir().join(format!("log-cli-{}-{}-{}", name, std::process::id(), n));
+
+        fs::create_dir_all(&path).unwrap();
+
+        TestDir(path)
+    }
+
+    /// Path of a file in the directory.
+    pub(crate) fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.0.join(path)
+    }
+
+    /// Path of the directory.
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.0).ok();
+    }
+}
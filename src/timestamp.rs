@@ -0,0 +1,682 @@
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Utc,
+};
+use chrono_tz::Tz;
+use std::{borrow::Cow, fmt};
+
+/// Formats tried for a timestamp written as a single token (ex: 2022-01-01T07:00:00).
+const DATETIME_FORMATS: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"];
+
+/// Formats tried for the time token following a date token (ex: 2022-01-01 07:00:00,0).
+const TIME_FORMATS: [&str; 2] = ["%H:%M:%S%.f", "%H:%M"];
+
+/// Format of timestamps rewritten into another zone.
+const OUTPUT_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f%:z";
+
+/// A timezone timestamps are read or displayed in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Zone {
+    #[default]
+    Utc,
+    /// The timezone of the system.
+    Local,
+    Fixed(FixedOffset),
+    /// A zone of the IANA database (ex: Europe/Paris).
+    Named(Tz),
+}
+
+impl Zone {
+    /// Parse a zone name (ex: UTC, local, +02:00 or America/New_York).
+    pub fn parse(name: &str) -> Option<Zone> {
+        match name.to_ascii_lowercase().as_str() {
+            "utc" | "z" => Some(Zone::Utc),
+            "local" => Some(Zone::Local),
+            _ => parse_offset(name)
+                .map(Zone::Fixed)
+                .or_else(|| name.parse().ok().map(Zone::Named)),
+        }
+    }
+
+    /// Offset of the zone at a time written in the zone. Times repeated when clocks go back take
+    /// the earlier offset, and times skipped when clocks go forward take the offset before the
+    /// change.
+    fn local_offset(&self, dt: &NaiveDateTime) -> FixedOffset {
+        fn offset<T: TimeZone>(tz: &T, dt: &NaiveDateTime) -> FixedOffset {
+            match tz.offset_from_local_datetime(dt).earliest() {
+                Some(offset) => offset.fix(),
+                None => tz.offset_from_utc_datetime(dt).fix(),
+            }
+        }
+
+        match self {
+            Zone::Utc => FixedOffset::east(0),
+            Zone::Local => offset(&Local, dt),
+            Zone::Fixed(fixed) => *fixed,
+            Zone::Named(tz) => offset(tz, dt),
+        }
+    }
+
+    /// Offset of the zone at a UTC time.
+    fn utc_offset(&self, dt: &NaiveDateTime) -> FixedOffset {
+        match self {
+            Zone::Utc => FixedOffset::east(0),
+            Zone::Local => Local.offset_from_utc_datetime(dt).fix(),
+            Zone::Fixed(fixed) => *fixed,
+            Zone::Named(tz) => tz.offset_from_utc_datetime(dt).fix(),
+        }
+    }
+
+    /// Convert a time written in the zone to UTC.
+    pub(crate) fn to_utc(self, dt: NaiveDateTime) -> NaiveDateTime {
+        dt - Duration::seconds(self.local_offset(&dt).local_minus_utc().into())
+    }
+
+    /// Convert a UTC time to the zone.
+    pub(crate) fn localize(&self, dt: NaiveDateTime) -> DateTime<FixedOffset> {
+        DateTime::<Utc>::from_utc(dt, Utc).with_timezone(&self.utc_offset(&dt))
+    }
+}
+
+impl fmt::Display for Zone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Zone::Utc => write!(f, "UTC"),
+            Zone::Local => write!(f, "local"),
+            Zone::Fixed(fixed) => write!(f, "{}", fixed),
+            Zone::Named(tz) => write!(f, "{}", tz.name()),
+        }
+    }
+}
+
+/// Tokens from the start of a line a timestamp can start at.
+const SEARCHED_TOKENS: usize = 4;
+
+/// Tokens a timestamp can span at most (ex: Tue, 1 Jul 2003 10:52:37 +0200).
+const MAX_SPAN: usize = 6;
+
+/// A timestamp found in a line.
+struct Found {
+    datetime: NaiveDateTime,
+    /// UTC offset written with the timestamp.
+    offset: Option<FixedOffset>,
+    start: usize,
+    end: usize,
+}
+
+impl Found {
+    /// The timestamp in UTC. Timestamps without an offset are read in `zone`.
+    fn utc(&self, zone: &Zone) -> NaiveDateTime {
+        match self.offset {
+            Some(offset) => self.datetime - Duration::seconds(offset.local_minus_utc().into()),
+            None => zone.to_utc(self.datetime),
+        }
+    }
+}
+
+/// A timestamp matched from tokens: the time as written, its offset, and the first and last
+/// tokens it was parsed from.
+type Matched<'a> = (NaiveDateTime, Option<FixedOffset>, &'a str, &'a str);
+
+/// Reads timestamps from log lines. Timestamps are found among the first tokens of a line and
+/// their format is recognized, unless a strftime format is given. Timestamps without an offset are
+/// read in a zone.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Parser {
+    format: Option<String>,
+    zone: Zone,
+}
+
+impl Parser {
+    /// Set the strftime format timestamps are parsed with and return a new `Parser`.
+    pub(crate) fn with_format(self, format: String) -> Parser {
+        let mut parser = self;
+
+        parser.format = Some(format.split_whitespace().collect::<Vec<_>>().join(" "));
+
+        parser
+    }
+
+    /// Set the zone timestamps without an offset are read in and return a new `Parser`.
+    pub(crate) fn with_zone(self, zone: Zone) -> Parser {
+        let mut parser = self;
+
+        parser.zone = zone;
+
+        parser
+    }
+
+    /// Access the zone timestamps without an offset are read in.
+    pub(crate) fn zone(&self) -> &Zone {
+        &self.zone
+    }
+
+    /// Parse the timestamp of a line as written. Dates without a time start at midnight.
+    pub fn datetime(&self, line: &str) -> Option<NaiveDateTime> {
+        self.find(line).map(|ts| ts.datetime)
+    }
+
+    /// Parse the timestamp of a line and convert it to UTC, so timestamps written in different
+    /// zones can be compared.
+    pub(crate) fn instant(&self, line: &str) -> Option<NaiveDateTime> {
+        self.find(line).map(|ts| ts.utc(&self.zone))
+    }
+
+    /// Rewrite the timestamp of a line into the `output` zone. Lines without a timestamp are
+    /// returned unchanged.
+    pub(crate) fn convert<'a>(&self, line: &'a str, output: &Zone) -> Cow<'a, str> {
+        match self.find(line) {
+            Some(ts) => {
+                let converted = output.localize(ts.utc(&self.zone)).format(OUTPUT_FORMAT);
+
+                Cow::Owned(format!(
+                    "{}{}{}",
+                    &line[..ts.start],
+                    converted,
+                    &line[ts.end..]
+                ))
+            }
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    /// Remove the timestamp of a line, with the brackets around it and the whitespace following
+    /// it.
+    pub(crate) fn strip<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let ts = match self.find(line) {
+            Some(ts) => ts,
+            None => return Cow::Borrowed(line),
+        };
+        let (mut start, mut end) = (ts.start, ts.end);
+
+        if line[..start].ends_with('[') && line[end..].starts_with(']') {
+            start -= 1;
+            end += 1;
+        }
+
+        let rest = line[end..].trim_start();
+
+        match start {
+            0 => Cow::Borrowed(rest),
+            _ => Cow::Owned(format!("{}{}", &line[..start], rest)),
+        }
+    }
+
+    /// Find the timestamp starting earliest among the first tokens of a line.
+    fn find(&self, line: &str) -> Option<Found> {
+        let mut tokens = [""; SEARCHED_TOKENS + MAX_SPAN];
+        let mut len = 0;
+
+        for (slot, token) in tokens.iter_mut().zip(line.split_whitespace()) {
+            *slot = token;
+            len += 1;
+        }
+
+        let tokens = &tokens[..len];
+        let offset = |token: &str| token.as_ptr() as usize - line.as_ptr() as usize;
+
+        (0..tokens.len().min(SEARCHED_TOKENS)).find_map(|i| {
+            let (datetime, tz, first, last) = match &self.format {
+                Some(format) => match_format(&tokens[i..], format),
+                None => match_auto(&tokens[i..], i == 0),
+            }?;
+
+            Some(Found {
+                datetime,
+                offset: tz,
+                start: offset(first),
+                end: offset(last) + last.len(),
+            })
+        })
+    }
+}
+
+/// Match a timestamp in a recognized format at the start of `tokens`. Epoch timestamps are only
+/// matched at the start of a line, where they can't be mistaken for other numbers.
+fn match_auto<'a>(tokens: &[&'a str], first: bool) -> Option<Matched<'a>> {
+    match_iso(tokens)
+        .or_else(|| match_rfc2822(tokens))
+        .or_else(|| match_apache(tokens))
+        .or_else(|| match_syslog(tokens))
+        .or_else(|| first.then(|| match_epoch(tokens[0])).flatten())
+}
+
+/// Match an ISO 8601 or RFC 3339 timestamp (ex: 2022-01-01T07:00:00Z), or a date followed by a
+/// time (ex: 2022-01-01 07:00:00,0 as logged by Python).
+fn match_iso<'a>(tokens: &[&'a str]) -> Option<Matched<'a>> {
+    let first = trim(tokens.first()?);
+
+    // Dates are written as %Y-%m-%d.
+    if !first.starts_with(|c: char| c.is_ascii_digit()) || first.as_bytes().get(4) != Some(&b'-') {
+        return None;
+    }
+
+    // Python's logging module separates fractional seconds with a comma.
+    let value = first.replace(',', ".");
+
+    let (datetime, offset, last, used) = match value.split_once('T') {
+        Some((date, time)) => {
+            let (time, offset) = split_offset(time);
+            let value = format!("{}T{}", date, time);
+            let dt = DATETIME_FORMATS
+                .iter()
+                .find_map(|f| NaiveDateTime::parse_from_str(&value, f).ok())?;
+
+            (dt, offset, first, 1)
+        }
+        None => {
+            let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d").ok()?;
+            let time = tokens.get(1).map(|t| trim(t)).and_then(|t| {
+                let (value, offset) = split_offset(t);
+                let value = value.replace(',', ".");
+
+                TIME_FORMATS
+                    .iter()
+                    .find_map(|f| NaiveTime::parse_from_str(&value, f).ok())
+                    .map(|time| (time, offset, t))
+            });
+
+            match time {
+                Some((time, offset, t)) => (date.and_time(time), offset, t, 2),
+                None => return Some((date.and_hms(0, 0, 0), None, first, first)),
+            }
+        }
+    };
+
+    // An offset can follow the time as its own token (ex: 07:00:00 +0200).
+    match (offset, tokens.get(used).map(|t| trim(t))) {
+        (None, Some(t)) => match parse_zone_token(t) {
+            Some(offset) => Some((datetime, Some(offset), first, t)),
+            None => Some((datetime, None, first, last)),
+        },
+        (offset, _) => Some((datetime, offset, first, last)),
+    }
+}
+
+/// Match an RFC 2822 timestamp (ex: Tue, 1 Jul 2003 10:52:37 +0200).
+fn match_rfc2822<'a>(tokens: &[&'a str]) -> Option<Matched<'a>> {
+    let weekday = usize::from(tokens.first()?.ends_with(','));
+    let parts = tokens.get(..weekday + 5)?;
+
+    // Dates are written as day, month and year (ex: 1 Jul 2003).
+    if !parts[weekday].starts_with(|c: char| c.is_ascii_digit())
+        || !parts[weekday + 1].starts_with(|c: char| c.is_ascii_alphabetic())
+    {
+        return None;
+    }
+
+    let (first, last) = (trim(parts[0]), trim(parts[weekday + 4]));
+    let mut words = parts.to_vec();
+    words[0] = first;
+    words[weekday + 4] = last;
+
+    let dt = DateTime::parse_from_rfc2822(&words.join(" ")).ok()?;
+
+    Some((dt.naive_local(), Some(*dt.offset()), first, last))
+}
+
+/// Match an Apache common log timestamp (ex: [10/Oct/2000:13:55:36 -0700]).
+fn match_apache<'a>(tokens: &[&'a str]) -> Option<Matched<'a>> {
+    let first = trim(tokens.first()?);
+
+    if !first.starts_with(|c: char| c.is_ascii_digit()) || !first.contains('/') {
+        return None;
+    }
+
+    let dt = NaiveDateTime::parse_from_str(first, "%d/%b/%Y:%H:%M:%S").ok()?;
+    let offset = tokens
+        .get(1)
+        .map(|t| trim(t))
+        .and_then(|t| parse_offset(t).map(|offset| (offset, t)));
+
+    Some(match offset {
+        Some((offset, t)) => (dt, Some(offset), first, t),
+        None => (dt, None, first, first),
+    })
+}
+
+/// Match a syslog timestamp (ex: Oct 11 22:14:15). Syslog doesn't log the year, so timestamps are
+/// read in the current year, or the year before if that would put them in the future.
+fn match_syslog<'a>(tokens: &[&'a str]) -> Option<Matched<'a>> {
+    // Messages sent over the network start with a priority (ex: <34>Oct 11 22:14:15).
+    let month = tokens.first()?;
+    let month = month
+        .strip_prefix('<')
+        .and_then(|m| m.split_once('>'))
+        .map_or(*month, |(_, m)| m);
+    let day = tokens.get(1)?;
+    let last = trim(tokens.get(2)?);
+
+    if !month.starts_with(|c: char| c.is_ascii_alphabetic()) || !last.contains(':') {
+        return None;
+    }
+
+    let time = NaiveTime::parse_from_str(last, "%H:%M:%S%.f").ok()?;
+    let now = Local::now().naive_local();
+    let date = |year: i32| {
+        NaiveDate::parse_from_str(&format!("{} {} {}", year, month, day), "%Y %b %d").ok()
+    };
+
+    let mut dt = date(now.year())?.and_time(time);
+    if dt > now + Duration::days(1) {
+        dt = date(now.year() - 1)?.and_time(time);
+    }
+
+    Some((dt, None, month, last))
+}
+
+/// Match an epoch timestamp in seconds (ex: 1641020400 or 1641020400.5) or milliseconds (ex:
+/// 1641020400000).
+fn match_epoch(token: &str) -> Option<Matched<'_>> {
+    let value = trim(token);
+    let (secs, frac) = value.split_once('.').unwrap_or((value, ""));
+
+    if !(secs.bytes().all(|b| b.is_ascii_digit()) && frac.bytes().all(|b| b.is_ascii_digit())) {
+        return None;
+    }
+
+    let dt = match (secs.len(), frac.len()) {
+        (10, _) => {
+            let nanos = format!("{:0<9}", &frac[..frac.len().min(9)]).parse().ok()?;
+            NaiveDateTime::from_timestamp_opt(secs.parse().ok()?, nanos)?
+        }
+        (13, 0) => {
+            let millis: i64 = secs.parse().ok()?;
+            NaiveDateTime::from_timestamp_opt(millis / 1000, (millis % 1000) as u32 * 1_000_000)?
+        }
+        _ => return None,
+    };
+
+    Some((dt, Some(FixedOffset::east(0)), value, value))
+}
+
+/// Match a timestamp written in a strftime format at the start of `tokens`. Brackets around the
+/// timestamp are ignored if the format doesn't include them.
+fn match_format<'a>(tokens: &[&'a str], format: &str) -> Option<Matched<'a>> {
+    let n = format.split(' ').count();
+    let parts = tokens.get(..n)?;
+
+    [false, true].into_iter().find_map(|trimmed| {
+        let mut words = parts.to_vec();
+
+        if trimmed {
+            words[0] = words[0].trim_start_matches('[');
+            words[n - 1] = words[n - 1].trim_end_matches(']');
+        }
+
+        let value = words.join(" ");
+
+        let (datetime, offset) = if let Ok(dt) = DateTime::parse_from_str(&value, format) {
+            (dt.naive_local(), Some(*dt.offset()))
+        } else if let Ok(dt) = NaiveDateTime::parse_from_str(&value, format) {
+            (dt, None)
+        } else {
+            let date = NaiveDate::parse_from_str(&value, format).ok()?;
+            (date.and_hms(0, 0, 0), None)
+        };
+
+        Some((datetime, offset, words[0], words[n - 1]))
+    })
+}
+
+/// Remove brackets, and a trailing comma or colon, around a token.
+fn trim(token: &str) -> &str {
+    token
+        .trim_start_matches('[')
+        .trim_end_matches([',', ':'])
+        .trim_end_matches(']')
+}
+
+/// Split an offset from the end of a time (ex: 07:00:00Z or 07:00:00-05:00).
+fn split_offset(time: &str) -> (&str, Option<FixedOffset>) {
+    if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        return (time, Some(FixedOffset::east(0)));
+    }
+
+    match time.rfind(['+', '-']) {
+        Some(i) if i > 0 => match parse_offset(&time[i..]) {
+            Some(offset) => (&time[..i], Some(offset)),
+            None => (time, None),
+        },
+        _ => (time, None),
+    }
+}
+
+/// Parse a token following a time as a UTC offset (ex: Z, UTC or +0200).
+fn parse_zone_token(token: &str) -> Option<FixedOffset> {
+    match token {
+        "Z" | "UTC" | "GMT" => Some(FixedOffset::east(0)),
+        _ => parse_offset(token),
+    }
+}
+
+/// Parse a UTC offset (ex: +02:00, +0200 or -05).
+fn parse_offset(value: &str) -> Option<FixedOffset> {
+    let sign = match value.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = value[1..].replace(':', "");
+
+    if !matches!(digits.len(), 2 | 4) || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits.get(2..).map_or(Ok(0), str::parse).ok()?;
+
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parse a user provided date or datetime. Dates passed as an `upper` bound include the whole
+/// day.
+pub fn parse_bound(value: &str, upper: bool) -> Option<NaiveDateTime> {
+    let value = value.trim().replace(',', ".");
+
+    if let Ok(date) = NaiveDate::parse_from_str(&value, "%Y-%m-%d") {
+        let start = date.and_hms(0, 0, 0);
+        return Some(if upper {
+            start + Duration::days(1) - Duration::nanoseconds(1)
+        } else {
+            start
+        });
+    }
+
+    let (date, time) = value.split_once([' ', 'T']).unwrap_or((&value, ""));
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    let time = TIME_FORMATS
+        .iter()
+        .find_map(|f| NaiveTime::parse_from_str(time.trim(), f).ok())?;
+
+    Some(date.and_time(time))
+}
+
+/// Units durations can be written in, longest first.
+const DURATION_UNITS: [(&str, i64); 5] = [
+    ("d", 86_400_000),
+    ("h", 3_600_000),
+    ("m", 60_000),
+    ("s", 1_000),
+    ("ms", 1),
+];
+
+/// Parse a user provided duration made of numbers and units (ex: 5m, 90s or 1h30m).
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let mut rest = value.trim();
+    let mut millis: i64 = 0;
+
+    if rest.is_empty() {
+        return None;
+    }
+
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let n: i64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let scale = DURATION_UNITS
+            .iter()
+            .find(|(name, _)| *name == &rest[..unit])
+            .map(|(_, scale)| scale)?;
+        rest = &rest[unit..];
+
+        millis = millis.checked_add(n.checked_mul(*scale)?)?;
+    }
+
+    Some(Duration::milliseconds(millis))
+}
+
+/// Describe a duration in its largest units (ex: 1h 30m or 250ms). Negative durations are
+/// described by their length.
+pub(crate) fn describe_duration(duration: Duration) -> String {
+    let mut millis = duration.num_milliseconds().abs();
+
+    if millis < 1_000 {
+        return format!("{}ms", millis);
+    }
+
+    let mut parts = vec![];
+
+    // Milliseconds are dropped from durations of a second or more.
+    for (name, scale) in &DURATION_UNITS[..4] {
+        if millis >= *scale {
+            parts.push(format!("{}{}", millis / scale, name));
+            millis %= scale;
+        }
+    }
+
+    parts.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_timestamps() {
+        let parser = Parser::default();
+        let datetime = |line| parser.datetime(line).unwrap().to_string();
+
+        assert_eq!(
+            datetime("2022-01-01 07:00:00,5 [info] module1"),
+            "2022-01-01 07:00:00.500"
+        );
+        assert_eq!(datetime("2022-01-02"), "2022-01-02 00:00:00");
+        assert_eq!(
+            datetime("[2022-01-01T07:00:00Z] started"),
+            "2022-01-01 07:00:00"
+        );
+        assert_eq!(
+            datetime("Tue, 1 Jul 2003 10:52:37 +0200 mail sent"),
+            "2003-07-01 10:52:37"
+        );
+        assert_eq!(
+            datetime("127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET / HTTP/1.0\""),
+            "2000-10-10 13:55:36"
+        );
+        assert!(datetime("<34>Oct 11 22:14:15 mymachine su: failed").ends_with("10-11 22:14:15"));
+        assert_eq!(datetime("1641020400.5 started"), "2022-01-01 07:00:00.500");
+        assert_eq!(datetime("1641020400000 started"), "2022-01-01 07:00:00");
+        assert_eq!(
+            datetime("INFO 2022-01-01 07:00:00 started"),
+            "2022-01-01 07:00:00"
+        );
+        assert!(parser.datetime("[info] module1 1641020400").is_none());
+
+        assert_eq!(
+            parser.strip("2022-01-01 07:00:00,5  [info] module1"),
+            "[info] module1"
+        );
+        assert_eq!(
+            parser.strip("INFO [2022-01-01 07:00:00] started"),
+            "INFO started"
+        );
+    }
+
+    #[test]
+    fn parse_timestamp_format() {
+        let parser = Parser::default().with_format("%d.%m.%Y %H:%M:%S".to_string());
+
+        assert_eq!(
+            parser
+                .datetime("[01.02.2022 07:00:00] started")
+                .unwrap()
+                .to_string(),
+            "2022-02-01 07:00:00"
+        );
+        assert!(parser.datetime("2022-01-01 07:00:00 started").is_none());
+
+        let parser = Parser::default().with_format("%s".to_string());
+        assert_eq!(
+            parser.instant("1641020400 started").unwrap().to_string(),
+            "2022-01-01 07:00:00"
+        );
+    }
+
+    #[test]
+    fn parse_bounds() {
+        assert_eq!(
+            parse_bound("2022-01-01", true).unwrap().to_string(),
+            "2022-01-01 23:59:59.999999999"
+        );
+        assert_eq!(
+            parse_bound("2022-01-01T07:30", false).unwrap().to_string(),
+            "2022-01-01 07:30:00"
+        );
+        assert!(parse_bound("2022-01-01 later", false).is_none());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("5m"), Some(Duration::minutes(5)));
+        assert_eq!(parse_duration("1h30m"), Some(Duration::minutes(90)));
+        assert_eq!(parse_duration("250ms"), Some(Duration::milliseconds(250)));
+        assert!(parse_duration("5").is_none());
+        assert!(parse_duration("m").is_none());
+        assert_eq!(describe_duration(Duration::seconds(-3_723)), "1h 2m 3s");
+        assert_eq!(describe_duration(Duration::milliseconds(250)), "250ms");
+    }
+
+    #[test]
+    fn convert_between_zones() {
+        let utc = Parser::default();
+        let paris = Parser::default().with_zone(Zone::parse("Europe/Paris").unwrap());
+
+        assert_eq!(
+            utc.instant("2022-01-01T07:00:00+02:00 [info]")
+                .unwrap()
+                .to_string(),
+            "2022-01-01 05:00:00"
+        );
+        assert_eq!(
+            paris
+                .instant("2022-07-01 07:00:00,5 -0500 [info]")
+                .unwrap()
+                .to_string(),
+            "2022-07-01 12:00:00.500"
+        );
+        assert_eq!(
+            paris
+                .instant("2022-07-01 07:00:00 [info]")
+                .unwrap()
+                .to_string(),
+            "2022-07-01 05:00:00"
+        );
+        assert_eq!(
+            utc.convert("2022-01-01T07:00:00Z [info] module1", paris.zone()),
+            "2022-01-01 08:00:00+01:00 [info] module1"
+        );
+        assert_eq!(utc.strip("2022-01-01 07:00:00 +02:00 [info]"), "[info]");
+        assert_eq!(
+            Zone::parse("-05:00"),
+            Some(Zone::Fixed(FixedOffset::west(5 * 3600)))
+        );
+        assert!(Zone::parse("Mars/Olympus").is_none());
+    }
+}
@@ -0,0 +1,826 @@
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, ClearType},
+};
+use log_cli::{timestamp, Encoding, Error, Instructions, Query, Result};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Lines of context displayed around matches when context is toggled on.
+const CONTEXT_LINES: usize = 3;
+
+/// Lines scanned for filter matches before checking for input again.
+const SCAN_BUDGET: usize = 100_000;
+
+/// Rows searched at a time when looking for a search term.
+const SEARCH_BLOCK: usize = 256;
+
+/// Number of lines between the offsets kept by a `LineIndex`.
+const CHECKPOINT_INTERVAL: usize = 256;
+
+/// Input being edited in the prompt bar, if any.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// Navigation keys are active.
+    Normal,
+    /// Editing the filter keywords. The filter is applied as it is typed.
+    Filter,
+    /// Editing an incremental search term.
+    Search,
+    /// Editing a line number or timestamp to jump to.
+    Jump,
+}
+
+/// A displayed line number and whether it matched the filter. Context rows are not matches.
+type Row = (usize, bool);
+
+/// Sparse table of line start offsets for a file. Only every `CHECKPOINT_INTERVAL`th offset is
+/// kept so files far larger than memory can be paged through by line number.
+pub(crate) struct LineIndex {
+    checkpoints: Vec<u64>,
+    len: usize,
+}
+
+impl LineIndex {
+    /// Build a `LineIndex` by scanning a file once.
+    pub(crate) fn build<P>(path: P) -> io::Result<LineIndex>
+    where
+        P: AsRef<Path>,
+    {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut checkpoints = vec![];
+        let mut buf = vec![];
+        let mut offset = 0;
+        let mut len = 0;
+
+        loop {
+            buf.clear();
+            let n = reader.read_until(b'\n', &mut buf)?;
+
+            if n == 0 {
+                break;
+            }
+
+            if len % CHECKPOINT_INTERVAL == 0 {
+                checkpoints.push(offset);
+            }
+
+            offset += n as u64;
+            len += 1;
+        }
+
+        Ok(LineIndex { checkpoints, len })
+    }
+
+    /// Number of lines in the indexed file.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Position a reader of the indexed file at the start of line `ln`.
+    pub(crate) fn seek<R>(&self, reader: &mut R, ln: usize) -> io::Result<()>
+    where
+        R: BufRead + Seek,
+    {
+        let checkpoint = (ln / CHECKPOINT_INTERVAL).min(self.checkpoints.len().saturating_sub(1));
+        let offset = self.checkpoints.get(checkpoint).copied().unwrap_or(0);
+
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut buf = vec![];
+        for _ in checkpoint * CHECKPOINT_INTERVAL..ln {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Read the next line from a reader without its line ending, as the query engine reads it.
+/// Return `None` at the end of the reader.
+fn read_line<R>(reader: &mut R, buf: &mut Vec<u8>, encoding: Encoding) -> io::Result<Option<String>>
+where
+    R: BufRead,
+{
+    buf.clear();
+
+    if reader.read_until(b'\n', buf)? == 0 {
+        return Ok(None);
+    }
+    if buf.ends_with(b"\n") {
+        buf.pop();
+    }
+    if buf.ends_with(b"\r") {
+        buf.pop();
+    }
+
+    Ok(Some(encoding.decode(buf).0))
+}
+
+/// Rows of a filtered view. Rows are found lazily by scanning the file, so only as much of the
+/// file as is needed to fill the screen is read.
+struct Rows {
+    query: Query,
+    encoding: Encoding,
+    context: usize,
+    rows: Vec<Row>,
+    reader: BufReader<File>,
+    next: usize,
+    after: usize,
+    before: VecDeque<usize>,
+    done: bool,
+}
+
+impl Rows {
+    /// Create `Rows` for lines of a file kept by a query.
+    fn new(path: &PathBuf, query: Query, encoding: Encoding, context: usize) -> Result<Rows> {
+        Ok(Rows {
+            query,
+            encoding,
+            context,
+            rows: vec![],
+            reader: BufReader::new(File::open(path)?),
+            next: 0,
+            after: 0,
+            before: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    /// Scan until at least `n` rows are found, the file ends, or `budget` lines were scanned.
+    fn scan(&mut self, n: usize, budget: usize) -> Result<()> {
+        let mut buf = vec![];
+        let mut scanned = 0;
+
+        while !self.done && self.rows.len() < n && scanned < budget {
+            let line = match read_line(&mut self.reader, &mut buf, self.encoding)? {
+                Some(line) => line,
+                None => {
+                    self.done = true;
+                    break;
+                }
+            };
+            let ln = self.next;
+
+            self.next += 1;
+            scanned += 1;
+
+            if self.query.matches_line(&line) {
+                self.rows.extend(self.before.drain(..).map(|l| (l, false)));
+                self.rows.push((ln, true));
+                self.after = self.context;
+            } else if self.after > 0 {
+                self.rows.push((ln, false));
+                self.after -= 1;
+            } else if self.context > 0 {
+                self.before.push_back(ln);
+                if self.before.len() > self.context {
+                    self.before.pop_front();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Terminal pager for a single log file. Lines are paged from disk using a `LineIndex`, and the
+/// filter bar is backed by the `Query` engine.
+pub(crate) struct View {
+    path: PathBuf,
+    instructions: Instructions,
+    encoding: Encoding,
+    index: LineIndex,
+    reader: BufReader<File>,
+    filter: String,
+    rows: Option<Rows>,
+    context: bool,
+    search: String,
+    bookmarks: BTreeSet<usize>,
+    top: usize,
+    cursor: usize,
+    height: usize,
+    mode: Mode,
+    input: String,
+    origin: (String, usize),
+    message: Option<String>,
+}
+
+impl View {
+    /// Open a `View` of the single path in `Instructions`. Keywords are used as the initial
+    /// filter.
+    pub(crate) fn open(instructions: Instructions) -> Result<View> {
+        let path = match instructions.sources() {
+            [source] if source.file().is_some() => source.name().to_path_buf(),
+            _ => return Err(Error::Args("view accepts exactly one path".to_string())),
+        };
+        let filter = instructions.keywords().join(" ");
+        let encoding = Query::build(instructions.clone())?.encoding(&path)?;
+
+        // Lines are paged by byte offset, so they must be separated by single bytes.
+        if !encoding.is_byte_oriented() {
+            return Err(Error::Format("view can't page UTF-16 files".to_string()));
+        }
+
+        let mut view = View {
+            index: LineIndex::build(&path).map_err(|e| Error::from(e).with_path(&path))?,
+            reader: File::open(&path)
+                .map(BufReader::new)
+                .map_err(|e| Error::from(e).with_path(&path))?,
+            path,
+            instructions,
+            encoding,
+            filter,
+            rows: None,
+            context: false,
+            search: String::new(),
+            bookmarks: BTreeSet::new(),
+            top: 0,
+            cursor: 0,
+            height: 1,
+            mode: Mode::Normal,
+            input: String::new(),
+            origin: (String::new(), 0),
+            message: None,
+        };
+        view.apply_filter()?;
+
+        Ok(view)
+    }
+
+    /// Rebuild rows from the current filter and context setting.
+    fn apply_filter(&mut self) -> Result<()> {
+        let keywords: Vec<String> = self.filter.split_whitespace().map(String::from).collect();
+
+        self.rows = if keywords.is_empty() {
+            None
+        } else {
+            let query = Query::build(self.instructions.clone().with_keywords(keywords))?;
+            let context = if self.context { CONTEXT_LINES } else { 0 };
+
+            Some(Rows::new(&self.path, query, self.encoding, context)?)
+        };
+        self.top = 0;
+        self.cursor = 0;
+
+        Ok(())
+    }
+
+    /// Make at least `n` rows available if possible. Return the number of rows available.
+    fn ensure(&mut self, n: usize, budget: usize) -> Result<usize> {
+        match self.rows.as_mut() {
+            None => Ok(self.index.len()),
+            Some(rows) => {
+                rows.scan(n, budget)?;
+                Ok(rows.rows.len())
+            }
+        }
+    }
+
+    /// Check if all rows of the view are known.
+    fn complete(&self) -> bool {
+        self.rows.as_ref().is_none_or(|rows| rows.done)
+    }
+
+    /// Access the row at position `i` if it has been found.
+    fn row(&self, i: usize) -> Option<Row> {
+        match self.rows.as_ref() {
+            None if i < self.index.len() => Some((i, false)),
+            None => None,
+            Some(rows) => rows.rows.get(i).copied(),
+        }
+    }
+
+    /// Find the position of the first row at or after line `ln`.
+    fn position_of(&mut self, ln: usize) -> Result<usize> {
+        if self.rows.is_none() {
+            return Ok(ln.min(self.index.len().saturating_sub(1)));
+        }
+
+        // Scan until a row past `ln` is found or the file ends.
+        loop {
+            let len = self.ensure(0, 0)?;
+            let found = self.row(len.saturating_sub(1)).is_some_and(|r| r.0 >= ln);
+
+            if found || self.complete() {
+                break;
+            }
+            self.ensure(len + 1, usize::MAX)?;
+        }
+
+        let rows = &self.rows.as_ref().unwrap().rows;
+
+        Ok(rows
+            .partition_point(|r| r.0 < ln)
+            .min(rows.len().saturating_sub(1)))
+    }
+
+    /// Read the text of rows `start..start + count` that have been found.
+    fn read_rows(&mut self, start: usize, count: usize) -> Result<Vec<(Row, String)>> {
+        let rows: Vec<Row> = (start..start + count).map_while(|i| self.row(i)).collect();
+        let mut res = Vec::with_capacity(rows.len());
+        let mut buf = vec![];
+        let mut next = None;
+
+        for row in rows {
+            // Only seek when rows are not contiguous.
+            if next != Some(row.0) {
+                self.index.seek(&mut self.reader, row.0)?;
+            }
+
+            let line = read_line(&mut self.reader, &mut buf, self.encoding)?.unwrap_or_default();
+            let line = match self.instructions.redactor() {
+                Some(redactor) => redactor.redact(&line).into_owned(),
+                None => line,
+            };
+            res.push((row, line));
+            next = Some(row.0 + 1);
+        }
+
+        Ok(res)
+    }
+
+    /// Move the cursor to row `i`, scrolling so it stays on screen.
+    fn set_cursor(&mut self, i: usize) -> Result<()> {
+        let len = self.ensure(i + 1, usize::MAX)?;
+
+        self.cursor = i.min(len.saturating_sub(1));
+
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + self.height {
+            self.top = self.cursor + 1 - self.height;
+        }
+
+        Ok(())
+    }
+
+    /// Move the cursor by `delta` rows.
+    fn move_cursor(&mut self, delta: isize) -> Result<()> {
+        self.set_cursor(self.cursor.saturating_add_signed(delta))
+    }
+
+    /// Move the cursor to the last row, scanning the rest of the file if filtered.
+    fn move_to_bottom(&mut self) -> Result<()> {
+        let len = self.ensure(usize::MAX, usize::MAX)?;
+
+        self.set_cursor(len.saturating_sub(1))
+    }
+
+    /// Line number under the cursor.
+    fn current_line(&self) -> usize {
+        self.row(self.cursor).map_or(0, |r| r.0)
+    }
+
+    /// Move the cursor to the first row at or after line `ln`.
+    fn jump_to_line(&mut self, ln: usize) -> Result<()> {
+        let i = self.position_of(ln)?;
+
+        self.set_cursor(i)?;
+        self.top = self.cursor;
+
+        Ok(())
+    }
+
+    /// Move the cursor to the first line logged at or after a timestamp. Lines are assumed to be
+    /// in time order, so the file is binary searched by line number.
+    fn jump_to_timestamp(&mut self, value: &str) -> Result<()> {
+        let target = match timestamp::parse_bound(value, false) {
+            Some(t) => t,
+            None => {
+                self.message = Some(format!("not a line number or timestamp: {}", value));
+                return Ok(());
+            }
+        };
+
+        let timestamps = self.instructions.timestamps().clone();
+        let (mut lower, mut upper) = (0, self.index.len());
+        let mut buf = vec![];
+
+        while lower < upper {
+            let mid = lower + (upper - lower) / 2;
+            self.index.seek(&mut self.reader, mid)?;
+
+            // Use the first timestamp found at or after `mid`.
+            let mut found = None;
+            let mut ln = mid;
+            while let Some(line) = read_line(&mut self.reader, &mut buf, self.encoding)? {
+                if let Some(t) = timestamps.datetime(&line) {
+                    found = Some(t);
+                    break;
+                }
+                ln += 1;
+            }
+
+            match found {
+                Some(t) if t < target => lower = ln + 1,
+                _ => upper = mid,
+            }
+        }
+
+        self.jump_to_line(lower)
+    }
+
+    /// Move the cursor to the next (or previous) row containing the search term, starting at
+    /// row `from`.
+    fn find(&mut self, from: usize, forward: bool) -> Result<()> {
+        if self.search.is_empty() {
+            return Ok(());
+        }
+
+        let mut start = from;
+
+        loop {
+            let block_start = if forward {
+                start
+            } else {
+                (start + 1).saturating_sub(SEARCH_BLOCK)
+            };
+            let len = self.ensure(block_start + SEARCH_BLOCK, usize::MAX)?;
+            let mut block = self.read_rows(block_start, SEARCH_BLOCK)?;
+
+            if !forward {
+                block.truncate(start + 1 - block_start);
+                block.reverse();
+            }
+
+            let found = block
+                .iter()
+                .position(|(_, line)| line.contains(&self.search));
+
+            if let Some(i) = found {
+                let row = if forward { block_start + i } else { start - i };
+                return self.set_cursor(row);
+            }
+
+            if forward && block_start + SEARCH_BLOCK >= len || !forward && block_start == 0 {
+                self.message = Some(format!("pattern not found: {}", self.search));
+                return Ok(());
+            }
+
+            start = if forward {
+                block_start + SEARCH_BLOCK
+            } else {
+                block_start - 1
+            };
+        }
+    }
+
+    /// Jump to the next (or previous) bookmark from the current line.
+    fn jump_to_bookmark(&mut self, forward: bool) -> Result<()> {
+        let ln = self.current_line();
+        let found = if forward {
+            self.bookmarks.range(ln + 1..).next().copied()
+        } else {
+            self.bookmarks.range(..ln).next_back().copied()
+        };
+
+        match found {
+            Some(b) => self.jump_to_line(b),
+            None => {
+                self.message = Some("no more bookmarks".to_string());
+                Ok(())
+            }
+        }
+    }
+
+    /// Update the view for a key press. Return true if the view should close.
+    fn handle_key(&mut self, key: KeyEvent) -> Result<bool> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Ok(true);
+        }
+
+        if self.mode != Mode::Normal {
+            self.handle_prompt_key(key)?;
+            return Ok(false);
+        }
+
+        self.message = None;
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(true),
+            KeyCode::Char('j') | KeyCode::Down => self.move_cursor(1)?,
+            KeyCode::Char('k') | KeyCode::Up => self.move_cursor(-1)?,
+            KeyCode::Char(' ') | KeyCode::PageDown => self.move_cursor(self.height as isize)?,
+            KeyCode::PageUp => self.move_cursor(-(self.height as isize))?,
+            KeyCode::Char('g') | KeyCode::Home => self.set_cursor(0)?,
+            KeyCode::Char('G') | KeyCode::End => self.move_to_bottom()?,
+            KeyCode::Char('n') => self.find(self.cursor + 1, true)?,
+            KeyCode::Char('N') if self.cursor > 0 => self.find(self.cursor - 1, false)?,
+            KeyCode::Char('b') => self.jump_to_bookmark(true)?,
+            KeyCode::Char('B') => self.jump_to_bookmark(false)?,
+            KeyCode::Char('m') => {
+                let ln = self.current_line();
+                if !self.bookmarks.remove(&ln) {
+                    self.bookmarks.insert(ln);
+                }
+            }
+            KeyCode::Char('c') => {
+                let ln = self.current_line();
+                self.context = !self.context;
+                self.apply_filter()?;
+                self.jump_to_line(ln)?;
+            }
+            KeyCode::Char('&') => self.open_prompt(Mode::Filter, self.filter.clone()),
+            KeyCode::Char('/') => self.open_prompt(Mode::Search, String::new()),
+            KeyCode::Char(':') => self.open_prompt(Mode::Jump, String::new()),
+            _ => {}
+        }
+
+        Ok(false)
+    }
+
+    /// Start editing a prompt, remembering state to restore if it is cancelled.
+    fn open_prompt(&mut self, mode: Mode, input: String) {
+        self.origin = (self.filter.clone(), self.cursor);
+        self.mode = mode;
+        self.input = input;
+    }
+
+    /// Update the prompt being edited for a key press.
+    fn handle_prompt_key(&mut self, key: KeyEvent) -> Result<()> {
+        match key.code {
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                if self.mode == Mode::Jump {
+                    let input = self.input.trim().to_string();
+                    match input.parse::<usize>() {
+                        Ok(ln) => self.jump_to_line(ln)?,
+                        Err(_) => self.jump_to_timestamp(&input)?,
+                    }
+                }
+                self.mode = Mode::Normal;
+                return Ok(());
+            }
+            KeyCode::Esc => {
+                let (filter, cursor) = self.origin.clone();
+                if self.mode == Mode::Filter {
+                    self.filter = filter;
+                    self.apply_filter()?;
+                }
+                if self.mode == Mode::Search {
+                    self.search.clear();
+                }
+                self.set_cursor(cursor)?;
+                self.mode = Mode::Normal;
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+
+        // Filters and searches are applied as they are typed.
+        match self.mode {
+            Mode::Filter => {
+                self.filter = self.input.clone();
+                self.apply_filter()?;
+            }
+            Mode::Search => {
+                self.search = self.input.clone();
+                self.find(self.origin.1, true)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Draw the visible rows and status bar.
+    fn draw<W>(&mut self, out: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        let (width, height) = terminal::size()?;
+        let width = width as usize;
+
+        self.height = (height as usize).saturating_sub(1).max(1);
+        self.ensure(self.top + self.height, SCAN_BUDGET)?;
+
+        // Pad line numbers to the width of the last line number.
+        let pad_len = self.index.len().saturating_sub(1).to_string().len();
+        let rows = self.read_rows(self.top, self.height)?;
+
+        queue!(out, terminal::Clear(ClearType::All))?;
+
+        for (i, ((ln, matched), line)) in rows.iter().enumerate() {
+            let marker = if self.bookmarks.contains(ln) {
+                '*'
+            } else {
+                ' '
+            };
+            let gutter = format!("{}ln{:0width$} ", marker, ln, width = pad_len);
+            let text: String = line
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .take(width.saturating_sub(gutter.len()))
+                .collect();
+
+            queue!(out, cursor::MoveTo(0, i as u16))?;
+
+            if self.top + i == self.cursor {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            if self.context && !matched {
+                queue!(out, SetAttribute(Attribute::Dim))?;
+            }
+
+            queue!(out, Print(gutter))?;
+            self.print_highlighted(out, &text)?;
+            queue!(out, SetAttribute(Attribute::Reset))?;
+        }
+
+        let status = match self.mode {
+            Mode::Filter => format!("&{}", self.input),
+            Mode::Search => format!("/{}", self.input),
+            Mode::Jump => format!(":{}", self.input),
+            Mode::Normal => self.status(),
+        };
+        let status: String = status.chars().take(width).collect();
+
+        queue!(
+            out,
+            cursor::MoveTo(0, self.height as u16),
+            SetAttribute(Attribute::Reverse),
+            Print(format!("{:width$}", status, width = width)),
+            SetAttribute(Attribute::Reset)
+        )?;
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Print text with occurrences of the search term highlighted.
+    fn print_highlighted<W>(&self, out: &mut W, text: &str) -> Result<()>
+    where
+        W: Write,
+    {
+        if self.search.is_empty() {
+            queue!(out, Print(text))?;
+            return Ok(());
+        }
+
+        let mut rest = text;
+        while let Some(i) = rest.find(&self.search) {
+            let end = i + self.search.len();
+            queue!(
+                out,
+                Print(&rest[..i]),
+                SetAttribute(Attribute::Bold),
+                SetAttribute(Attribute::Underlined),
+                Print(&rest[i..end]),
+                SetAttribute(Attribute::NoBold),
+                SetAttribute(Attribute::NoUnderline)
+            )?;
+            rest = &rest[end..];
+        }
+        queue!(out, Print(rest))?;
+
+        Ok(())
+    }
+
+    /// Status bar text for normal mode.
+    fn status(&self) -> String {
+        let mut status = format!(
+            "{}  ln{}/{}",
+            self.path.display(),
+            self.current_line(),
+            self.index.len()
+        );
+
+        if let Some(rows) = self.rows.as_ref() {
+            let matches = rows.rows.iter().filter(|r| r.1).count();
+            let more = if rows.done { "" } else { "+" };
+            status.push_str(&format!("  filter: {} ({}{})", self.filter, matches, more));
+        }
+        if self.context {
+            status.push_str("  [context]");
+        }
+        if let Some(message) = self.message.as_ref() {
+            status.push_str(&format!("  {}", message));
+        }
+
+        status
+    }
+
+    /// Run the view in the terminal until it is closed.
+    pub(crate) fn run(&mut self) -> Result<()> {
+        let mut out = io::stdout();
+
+        terminal::enable_raw_mode()?;
+        queue!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+
+        let res = self.event_loop(&mut out);
+
+        queue!(out, terminal::LeaveAlternateScreen, cursor::Show)?;
+        out.flush()?;
+        terminal::disable_raw_mode()?;
+
+        res
+    }
+
+    /// Draw and handle input until the view is closed. While a filter is still scanning, input is
+    /// polled without waiting so scanning continues between key presses.
+    fn event_loop<W>(&mut self, out: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        loop {
+            self.draw(out)?;
+
+            let scanning = !self.complete() && self.ensure(0, 0)? < self.top + self.height;
+            let timeout = if scanning { 0 } else { 250 };
+
+            if event::poll(Duration::from_millis(timeout))? {
+                if let Event::Key(key) = event::read()? {
+                    if self.handle_key(key)? {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+    use std::fs;
+
+    /// Write lines to a file in a temporary directory and open a `View` of it. The view reads
+    /// the file until the directory is dropped.
+    fn open_view(name: &str, lines: &[&str]) -> (TestDir, View) {
+        let dir = TestDir::new(&format!("view-{}", name));
+        let path = dir.join("view.log");
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let mut view = View::open(Instructions::new().add_path(path)).unwrap();
+        view.height = 2;
+
+        (dir, view)
+    }
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn filter_with_context() {
+        let (_dir, mut view) = open_view("filter", &["a", "b", "target", "c", "d", "e", "target"]);
+
+        view.filter = "target".to_string();
+        view.apply_filter().unwrap();
+        view.ensure(usize::MAX, usize::MAX).unwrap();
+        assert_eq!(view.rows.as_ref().unwrap().rows, vec![(2, true), (6, true)]);
+
+        view.handle_key(key('c')).unwrap();
+        view.ensure(usize::MAX, usize::MAX).unwrap();
+        let lns: Vec<usize> = view
+            .rows
+            .as_ref()
+            .unwrap()
+            .rows
+            .iter()
+            .map(|r| r.0)
+            .collect();
+        assert_eq!(lns, vec![0, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn jump_search_and_bookmark() {
+        let (_dir, mut view) = open_view(
+            "jump",
+            &[
+                "2022-01-01 07:00:00,0 start",
+                "2022-01-01 08:00:00,0 middle",
+                "2022-01-01 09:00:00,0 end",
+            ],
+        );
+
+        view.jump_to_timestamp("2022-01-01 07:30").unwrap();
+        assert_eq!(view.current_line(), 1);
+
+        view.jump_to_line(0).unwrap();
+        view.search = "end".to_string();
+        view.handle_key(key('n')).unwrap();
+        assert_eq!(view.current_line(), 2);
+
+        view.handle_key(key('m')).unwrap();
+        view.jump_to_line(0).unwrap();
+        view.handle_key(key('b')).unwrap();
+        assert_eq!(view.current_line(), 2);
+    }
+}